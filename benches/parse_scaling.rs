@@ -0,0 +1,95 @@
+//! Lexes and parses generated sources of growing size. The time per byte should stay
+//! roughly constant, anything growing with the input size points to quadratic behavior.
+//!
+//! Run with `cargo bench`.
+
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+#[path = "../src/java.rs"]
+mod java;
+#[path = "../src/diagnostic.rs"]
+mod diagnostic;
+#[path = "../src/lexer.rs"]
+mod lexer;
+#[path = "../src/parser.rs"]
+mod parser;
+
+use lexer::Lexer;
+use parser::Parser;
+
+const RUNS: usize = 5;
+
+fn generate_source(class_count: usize) -> String {
+    let mut source = String::new();
+
+    for i in 0..class_count {
+        source.push_str(&format!(r#"
+/** Generated class number {i} */
+@Entity
+public class Generated{i}<T extends Comparable<T>> extends Base implements Runnable {{
+    private static final String NAME = "generated {{ {i} }}";
+    protected java.util.Map<String, java.util.List<T>> items;
+    private int[] counts = {{ 1, 2, 3 }};
+
+    public Generated{i}(String name, T... values) {{
+        // comment with a brace }}
+        super(name);
+    }}
+
+    @Override
+    public void run() {{
+        Runnable r = new Runnable() {{
+            public void run() {{ System.out.println('}}' + "done"); }}
+        }};
+        for (int j = 0; j < 10; j++) {{ counts[j % 3] += j * 0x1F; }}
+        int[] copy = new int[counts.length];
+        T[][] grid = (T[][]) new Comparable[4][counts.length];
+    }}
+}}
+"#, i = i));
+    }
+
+    source
+}
+
+/// Fastest of a few runs, to keep scheduling noise out of the numbers.
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    (0..RUNS).map(|_| {
+                 let start = Instant::now();
+                 f();
+                 start.elapsed()
+             })
+             .min()
+             .unwrap()
+}
+
+fn main() {
+    println!("{:>8} {:>10} {:>12} {:>12} {:>14} {:>14}",
+             "classes", "bytes", "lex (ms)", "parse (ms)", "lex (ns/B)", "parse (ns/B)");
+
+    for &class_count in &[250, 500, 1000, 2000, 4000, 8000] {
+        let source = generate_source(class_count);
+
+        let lex_time = measure(|| {
+            let tokens = Lexer::new(&source).tokenize();
+            assert!(!tokens.is_empty());
+        });
+
+        let parse_time = measure(|| {
+            let mut parser = Parser::new(&source);
+            let unit = parser.parse_compilation_unit();
+            assert_eq!(unit.classes.len(), class_count);
+            assert!(parser.diagnostics.is_empty());
+        });
+
+        let bytes = source.len() as f64;
+        println!("{:>8} {:>10} {:>12.2} {:>12.2} {:>14.2} {:>14.2}",
+                 class_count, source.len(),
+                 lex_time.as_secs_f64() * 1e3,
+                 parse_time.as_secs_f64() * 1e3,
+                 lex_time.as_nanos() as f64 / bytes,
+                 parse_time.as_nanos() as f64 / bytes);
+    }
+}
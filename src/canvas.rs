@@ -0,0 +1,191 @@
+use crate::font::Fonts;
+
+/// Text styles of the diagram: bold headings, italic abstract and underlined static elements.
+/// Text is black unless given another color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub color: Color,
+}
+
+impl TextStyle {
+    pub fn new(bold: bool, italic: bool, underline: bool) -> Self {
+        Self {
+            bold, italic, underline, color: Color::BLACK
+        }
+    }
+
+    pub fn plain() -> Self {
+        Self::new(false, false, false)
+    }
+
+    pub fn with_color(self, color: Color) -> Self {
+        Self {
+            color, ..self
+        }
+    }
+}
+
+/// An RGBA color. The alpha channel is the opacity, so 255 covers whatever is below.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self::rgba(r, g, b, 255)
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self {
+            r, g, b, a
+        }
+    }
+
+    /// This color painted over `background` where `coverage` of 255 is full coverage,
+    /// like the gray values of a rendered glyph.
+    pub fn blend_over(self, background: Color, coverage: u8) -> Color {
+        let alpha = self.a as u32 * coverage as u32 / 255;
+        let below = background.a as u32 * (255 - alpha) / 255;
+        let out_a = alpha + below;
+
+        if out_a == 0 {
+            return Color::rgba(0, 0, 0, 0);
+        }
+
+        let channel = |top: u8, bottom: u8| ((top as u32 * alpha + bottom as u32 * below) / out_a) as u8;
+
+        Color::rgba(channel(self.r, background.r), channel(self.g, background.g), channel(self.b, background.b),
+                    out_a as u8)
+    }
+}
+
+/// Stroke pattern of a line: `on` pixels drawn, then `off` pixels left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dash {
+    pub on: u32,
+    pub off: u32,
+}
+
+impl Dash {
+    pub const SOLID: Dash = Dash::new(1, 0);
+
+    pub const fn new(on: u32, off: u32) -> Self {
+        Self {
+            on, off
+        }
+    }
+
+    pub fn is_solid(self) -> bool {
+        self.off == 0
+    }
+
+    /// Whether the pixel `distance` pixels along the line is drawn.
+    pub fn covers(self, distance: u32) -> bool {
+        self.is_solid() || distance % (self.on + self.off) < self.on
+    }
+}
+
+/// Decorations at the ends of UML edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrowHead {
+    /// Two strokes, for associations and dependencies.
+    Open,
+    /// For generalizations and realizations.
+    HollowTriangle,
+    /// At the whole of an aggregation.
+    HollowDiamond,
+    /// At the whole of a composition.
+    FilledDiamond,
+}
+
+/// Corners at the back of a shape of `length` pointing from `tail` to `tip`, `half_width` to either side.
+pub fn arrow_corners(tip: (i32, i32), tail: (i32, i32), length: i32, half_width: i32) -> ((i32, i32), (i32, i32)) {
+    let (dx, dy) = ((tip.0 - tail.0) as f32, (tip.1 - tail.1) as f32);
+    let len = (dx * dx + dy * dy).sqrt().max(1.0);
+    let (ux, uy) = (dx / len, dy / len);
+    let (length, half_width) = (length as f32, half_width as f32);
+
+    let base_x = tip.0 as f32 - ux * length;
+    let base_y = tip.1 as f32 - uy * length;
+    let left = ((base_x - uy * half_width).round() as i32, (base_y + ux * half_width).round() as i32);
+    let right = ((base_x + uy * half_width).round() as i32, (base_y - ux * half_width).round() as i32);
+
+    (left, right)
+}
+
+/// Drawing surface of the diagram, implemented by the raster `Image` and the `Svg` writer.
+/// Text is always measured with the freetype fonts, so both share the same layout.
+/// Shapes made of the primitives, like polylines and arrowheads, have default implementations
+/// a backend may replace with native ones.
+pub trait Canvas {
+    fn with_size(width: u32, height: u32) -> Self where Self: Sized;
+
+    /// Width of `text` as drawn by `draw_text`.
+    fn measure_text(text: &str, font_size: usize, fonts: &Fonts, style: TextStyle) -> u32 where Self: Sized;
+
+    /// Draws `text` with its top left corner at (`x`, `y`) and the baseline `font_size` below.
+    fn draw_text(&mut self, x: u32, y: u32, text: &str, font_size: usize, fonts: &Fonts, style: TextStyle);
+
+    /// A line at any angle, stroked with the `dash` pattern.
+    fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, dash: Dash, color: Color);
+
+    /// Connected lines through all `points`, like a routed edge.
+    fn draw_polyline(&mut self, points: &[(i32, i32)], dash: Dash, color: Color) {
+        for pair in points.windows(2) {
+            self.draw_line(pair[0].0, pair[0].1, pair[1].0, pair[1].1, dash, color);
+        }
+    }
+
+    fn draw_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color);
+
+    fn draw_filled_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color);
+
+    fn draw_circle(&mut self, cx: i32, cy: i32, radius: i32, color: Color);
+
+    /// Closed outline through all `points`.
+    fn draw_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        let mut closed = points.to_vec();
+        closed.extend(points.first());
+        self.draw_polyline(&closed, Dash::SOLID, color);
+    }
+
+    fn fill_polygon(&mut self, points: &[(i32, i32)], color: Color);
+
+    /// Draws `head` with its point at `tip`, pointing away from `tail`. The shape is `size` long.
+    fn draw_arrowhead(&mut self, tip: (i32, i32), tail: (i32, i32), head: ArrowHead, size: i32, color: Color) {
+        match head {
+            ArrowHead::Open => {
+                let (left, right) = arrow_corners(tip, tail, size, size / 2);
+                self.draw_polyline(&[left, tip, right], Dash::SOLID, color);
+            },
+            ArrowHead::HollowTriangle => {
+                let (left, right) = arrow_corners(tip, tail, size, size / 2);
+                // Hollow shapes cover whatever runs below them
+                self.fill_polygon(&[tip, left, right], Color::WHITE);
+                self.draw_polygon(&[tip, left, right], color);
+            },
+            ArrowHead::HollowDiamond | ArrowHead::FilledDiamond => {
+                let (left, right) = arrow_corners(tip, tail, size / 2, size / 3);
+                let (back, _) = arrow_corners(tip, tail, size, 0);
+                let diamond = [tip, left, back, right];
+
+                if head == ArrowHead::FilledDiamond {
+                    self.fill_polygon(&diamond, color);
+                } else {
+                    self.fill_polygon(&diamond, Color::WHITE);
+                }
+                self.draw_polygon(&diamond, color);
+            },
+        }
+    }
+}
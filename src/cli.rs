@@ -0,0 +1,347 @@
+use std::path::{Path, PathBuf};
+
+use crate::diagram::{DiagramOptions, LayoutStyle, NestingStyle};
+
+pub const EXIT_SUCCESS: i32 = 0;
+/// The input has errors, see the diagnostics.
+pub const EXIT_INVALID_INPUT: i32 = 1;
+/// The command line could not be understood.
+pub const EXIT_USAGE: i32 = 2;
+/// An input, output or font file could not be read or written.
+pub const EXIT_IO: i32 = 3;
+
+pub const USAGE: &str = "\
+UML-san draws UML class diagrams of Java code.
+
+USAGE:
+    rust-uml-for-java <COMMAND> [OPTIONS] <INPUT>...
+    rust-uml-for-java <INPUT> [FONT] [OPTIONS]
+
+COMMANDS:
+    render    Draw the diagram into a PNG, SVG or PPM file
+    check     Parse the input and only report diagnostics
+    export    Write the diagram in a text format, SVG or PlantUML
+    view      Show the diagram in a window
+    help      Print this help
+
+INPUT:
+    Java files and source roots, of which every .java file below is read. `-` reads a file from stdin.
+    Without a command, the diagram is shown like with `view`, or rendered if an output is given.
+
+OPTIONS:
+    -o, --output <PATH>             File to write, `-` for stdout
+                                    [render: diagram.<FORMAT>, export: stdout]
+        --format <FORMAT>           render: png, svg or ppm, export: svg or plantuml
+                                    [default: the extension of the output, else png or svg]
+        --font <FONT>               Font file, or `sans` or `mono` for a built-in font
+                                    [default: font.ttf if it exists, else sans]
+        --bold-font <PATH>          Bold face, found next to the font if left out
+        --italic-font <PATH>        Italic face, found next to the font if left out
+        --bold-italic-font <PATH>   Bold italic face, found next to the font if left out
+        --fallback-font <PATHS>     Comma separated fonts for characters the font lacks
+        --theme <THEME>             plain or color [default: plain]
+        --layout <LAYOUT>           layered or grid [default: layered]
+        --highlight <NAMES>         Comma separated simple or qualified names of classes to highlight
+        --stereotypes <NAMES>       Comma separated annotations drawn as stereotypes, * for all of them
+        --nested-inside             Draw nested classes inside their outer class
+        --fields-as-edges           Leave fields drawn as associations out of the attribute compartment
+        --ownership                 Draw final fields as compositions and collections as aggregations
+        --read-only                 Add {readOnly} to final fields
+        --strict                    Stop on errors in the input instead of drawing what could be parsed
+        --headless                  Render instead of showing the window, to save.ppm unless an output is given
+    -h, --help                      Print this help
+
+EXIT CODES:
+    0    Success
+    1    The input has errors, only for `check` and with --strict
+    2    The command line is invalid
+    3    A file could not be read or written
+";
+
+/// Options that take a value, either as `--name=value` or as `--name value`.
+const VALUE_OPTIONS: &[&str] = &[
+    "--output", "--format", "--font", "--bold-font", "--italic-font", "--bold-italic-font", "--fallback-font",
+    "--theme", "--layout", "--highlight", "--stereotypes",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Render,
+    Check,
+    Export,
+    View,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Svg,
+    Ppm,
+    PlantUml,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png" => Some(Format::Png),
+            "svg" => Some(Format::Svg),
+            "ppm" => Some(Format::Ppm),
+            "plantuml" | "puml" => Some(Format::PlantUml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Svg => "svg",
+            Format::Ppm => "ppm",
+            Format::PlantUml => "puml",
+        }
+    }
+
+    /// Text formats are the ones `export` writes.
+    pub fn is_text(self) -> bool {
+        self == Format::Svg || self == Format::PlantUml
+    }
+}
+
+pub struct Cli {
+    pub command: Command,
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<String>,
+    pub format: Option<Format>,
+    pub font: Option<String>,
+    pub bold_font: Option<String>,
+    pub italic_font: Option<String>,
+    pub bold_italic_font: Option<String>,
+    pub fallback_fonts: Vec<String>,
+    /// Stops on errors in the input instead of drawing what could be parsed.
+    pub strict: bool,
+    pub options: DiagramOptions,
+}
+
+impl Cli {
+    fn new(command: Command) -> Self {
+        Self {
+            command,
+            inputs: Vec::new(),
+            output: None,
+            format: None,
+            font: None,
+            bold_font: None,
+            italic_font: None,
+            bold_italic_font: None,
+            fallback_fonts: Vec::new(),
+            strict: false,
+            options: DiagramOptions::new(),
+        }
+    }
+
+    /// The format given with `--format`, else the one of the extension of the output.
+    pub fn output_format(&self) -> Format {
+        let from_extension = self.output.as_deref()
+                                 .and_then(|output| Path::new(output).extension())
+                                 .and_then(|extension| Format::from_name(&extension.to_str()?.to_lowercase()));
+
+        match (self.format, from_extension) {
+            (Some(format), _) | (None, Some(format)) => format,
+            (None, None) if self.command == Command::Export => Format::Svg,
+            (None, None) => Format::Png,
+        }
+    }
+
+    /// The file to write, `None` for stdout.
+    pub fn output_path(&self) -> Option<String> {
+        match (&self.output, self.command) {
+            (Some(output), _) if output == "-" => None,
+            (Some(output), _) => Some(output.clone()),
+            (None, Command::Export) => None,
+            (None, _) => Some(format!("diagram.{}", self.output_format().extension())),
+        }
+    }
+}
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').filter(|item| !item.is_empty()).map(|item| item.to_string()).collect()
+}
+
+/// Parses the arguments after the program name.
+pub fn parse_args(args: Vec<String>) -> Result<Cli, String> {
+    let mut args = args.into_iter().peekable();
+
+    let command = match args.peek().map(String::as_str) {
+        Some("render") => Some(Command::Render),
+        Some("check") => Some(Command::Check),
+        Some("export") => Some(Command::Export),
+        Some("view") => Some(Command::View),
+        Some("help") => Some(Command::Help),
+        _ => None,
+    };
+    if command.is_some() {
+        args.next();
+    }
+
+    let mut cli = Cli::new(command.unwrap_or(Command::View));
+    let mut positional = Vec::new();
+    let mut headless = false;
+
+    while let Some(arg) = args.next() {
+        let (name, inline_value) = if arg == "-h" || arg == "--help" {
+            cli.command = Command::Help;
+            continue;
+        } else if arg == "-o" {
+            ("--output".to_string(), None)
+        } else if arg.starts_with("--") {
+            match arg.find('=') {
+                Some(i) => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+                None => (arg.clone(), None),
+            }
+        } else if arg.starts_with('-') && arg != "-" {
+            return Err(format!("unknown option `{}`", arg));
+        } else {
+            positional.push(arg);
+            continue;
+        };
+
+        let value = if VALUE_OPTIONS.contains(&name.as_str()) {
+            match inline_value.or_else(|| args.next()) {
+                Some(value) => value,
+                None => return Err(format!("`{}` needs a value", name)),
+            }
+        } else if inline_value.is_some() {
+            return Err(format!("`{}` takes no value", name));
+        } else {
+            String::new()
+        };
+
+        match name.as_str() {
+            "--output" => cli.output = Some(value),
+            "--format" => match Format::from_name(&value) {
+                Some(format) => cli.format = Some(format),
+                None => return Err(format!("unknown format `{}`, expected png, svg, ppm or plantuml", value)),
+            },
+            "--font" => cli.font = Some(value),
+            "--bold-font" => cli.bold_font = Some(value),
+            "--italic-font" => cli.italic_font = Some(value),
+            "--bold-italic-font" => cli.bold_italic_font = Some(value),
+            "--fallback-font" => cli.fallback_fonts.extend(split_list(&value)),
+            "--theme" => match value.as_str() {
+                "plain" => cli.options.colored = false,
+                "color" => cli.options.colored = true,
+                _ => return Err(format!("unknown theme `{}`, expected plain or color", value)),
+            },
+            "--layout" => match value.as_str() {
+                "layered" => cli.options.layout = LayoutStyle::Layered,
+                "grid" => cli.options.layout = LayoutStyle::Grid,
+                _ => return Err(format!("unknown layout `{}`, expected layered or grid", value)),
+            },
+            "--highlight" => cli.options.highlighted_classes = split_list(&value),
+            "--stereotypes" => {
+                cli.options.stereotype_annotations = split_list(&value).iter()
+                                                                       .map(|n| n.trim_start_matches('@').to_string())
+                                                                       .collect();
+            },
+            "--nested-inside" => cli.options.nesting = NestingStyle::Inside,
+            "--fields-as-edges" => cli.options.fields_as_edges_only = true,
+            "--ownership" => cli.options.infer_ownership = true,
+            "--read-only" => cli.options.show_read_only = true,
+            "--strict" => cli.strict = true,
+            "--headless" => headless = true,
+            _ => return Err(format!("unknown option `{}`", name)),
+        }
+    }
+
+    if cli.command == Command::Help {
+        return Ok(cli);
+    }
+
+    if command.is_none() {
+        // The original form: the input, optionally followed by the font, shown in a window unless written to a file
+        if positional.len() > 2 {
+            return Err("expected the input and the font, use a command to pass several inputs".to_string());
+        }
+        if positional.len() == 2 && cli.font.is_none() {
+            cli.font = positional.pop();
+        }
+        if cli.output.is_some() || cli.format.is_some() {
+            cli.command = Command::Render;
+        }
+        if positional.is_empty() {
+            positional.push("sample.java".to_string());
+        }
+    }
+
+    if positional.is_empty() {
+        return Err("no input given, pass java files, source roots or `-` for stdin".to_string());
+    }
+    cli.inputs = positional.into_iter().map(PathBuf::from).collect();
+
+    // Kept from before the commands, when the window was skipped and only `save.ppm` written
+    if headless && cli.command == Command::View {
+        cli.command = Command::Render;
+        if cli.output.is_none() && cli.format.is_none() {
+            cli.output = Some("save.ppm".to_string());
+        }
+    }
+
+    match cli.command {
+        Command::Check | Command::View if cli.output.is_some() || cli.format.is_some() =>
+            Err("only `render` and `export` write a file".to_string()),
+        Command::Export if !cli.output_format().is_text() =>
+            Err(format!("`export` writes text formats, render {} with `render`", cli.output_format().extension())),
+        _ => Ok(cli),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Cli, String> {
+        parse_args(args.split_whitespace().map(str::to_string).collect())
+    }
+
+    #[test]
+    fn original_form() {
+        let cli = parse("Shop.java font.ttf").unwrap();
+        assert_eq!(cli.command, Command::View);
+        assert_eq!(cli.inputs, [PathBuf::from("Shop.java")]);
+        assert_eq!(cli.font.as_deref(), Some("font.ttf"));
+
+        assert_eq!(parse("").unwrap().inputs, [PathBuf::from("sample.java")]);
+        assert!(parse("a.java b.java font.ttf").is_err());
+
+        let cli = parse("Shop.java -o shop.svg").unwrap();
+        assert_eq!(cli.command, Command::Render);
+        assert_eq!(cli.output_format(), Format::Svg);
+    }
+
+    #[test]
+    fn headless_renders_to_save_ppm() {
+        let cli = parse("Shop.java --headless").unwrap();
+        assert_eq!(cli.command, Command::Render);
+        assert_eq!(cli.output_path().as_deref(), Some("save.ppm"));
+        assert_eq!(cli.output_format(), Format::Ppm);
+
+        let cli = parse("--headless -o shop.png Shop.java").unwrap();
+        assert_eq!(cli.output_path().as_deref(), Some("shop.png"));
+
+        let cli = parse("render --headless Shop.java").unwrap();
+        assert_eq!(cli.output_path().as_deref(), Some("diagram.png"));
+    }
+
+    #[test]
+    fn export_writes_text_formats() {
+        let cli = parse("export src").unwrap();
+        assert_eq!(cli.output_format(), Format::Svg);
+        assert_eq!(cli.output_path(), None);
+
+        assert_eq!(parse("export --format=plantuml src").unwrap().output_format(), Format::PlantUml);
+        assert_eq!(parse("export -o shop.puml src").unwrap().output_format(), Format::PlantUml);
+        assert!(parse("export -o shop.png src").is_err());
+        assert!(parse("export --format ppm src").is_err());
+        assert!(parse("check -o shop.svg src").is_err());
+    }
+}
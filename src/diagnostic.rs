@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(&self) -> &'static str {
+        match *self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub file: Option<PathBuf>,
+    /// 1-based line and column of the start of the span.
+    pub line: usize,
+    pub column: usize,
+    /// Byte offset of the span into the source.
+    pub start: usize,
+    /// The full source line containing `start`, used for the snippet in reports.
+    pub source_line: String,
+    /// Number of characters of the span on `source_line`.
+    width: usize,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String, source: &str, start: usize, end: usize) -> Self {
+        let before = &source[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+
+        let source_line = source[line_start..line_end].trim_end().to_string();
+        let width = source[start..end.clamp(start, line_end)].chars().count();
+
+        Self {
+            severity,
+            message,
+            file: None,
+            line: before.matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            start,
+            source_line,
+            width,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Formats the diagnostic like rustc does, with the offending line and a caret below the span.
+    pub fn render(&self) -> String {
+        let location = match &self.file {
+            Some(file) => format!("{}:{}:{}", file.display(), self.line, self.column),
+            None => format!("{}:{}", self.line, self.column),
+        };
+
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        // Copy tabs from the source line so the caret lines up in every terminal
+        let indent = self.source_line.chars()
+                                     .take(self.column - 1)
+                                     .map(|c| if c == '\t' { '\t' } else { ' ' })
+                                     .collect::<String>();
+        let line_length = self.source_line.chars().count();
+        let caret_count = self.width.min(line_length.saturating_sub(self.column - 1)).max(1);
+
+        format!("{}: {}\n{}--> {}\n{} |\n{} | {}\n{} | {}{}\n",
+                self.severity.label(), self.message,
+                gutter, location,
+                gutter,
+                line_number, self.source_line,
+                gutter, indent, "^".repeat(caret_count))
+    }
+}
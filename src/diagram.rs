@@ -0,0 +1,495 @@
+use std::cmp;
+
+use crate::java::*;
+use crate::relation::*;
+use crate::canvas::{ArrowHead, Canvas, Color, Dash, TextStyle};
+use crate::font::Fonts;
+use crate::layout;
+use crate::route::{self, Rect};
+
+
+const FONT_SIZE: u32 = 40;
+const HEADING_FONT_SIZE: u32 = 48;
+const UML_PADDING: u32 = 20;
+const UML_ITEM_HEIGHT: u32 = UML_PADDING * 2 + FONT_SIZE;
+const HEADING_HEIGHT: u32 = UML_PADDING * 2 + HEADING_FONT_SIZE;
+const ARROW_SIZE: i32 = 20;
+const DASHED: Dash = Dash::new(8, 8);
+const TEMPLATE_FONT_SIZE: u32 = 28;
+const TEMPLATE_PADDING: u32 = 6;
+const TEMPLATE_HEIGHT: u32 = TEMPLATE_PADDING * 2 + TEMPLATE_FONT_SIZE;
+const TEMPLATE_OVERHANG: u32 = UML_PADDING;
+const STEREOTYPE_HEIGHT: u32 = FONT_SIZE + UML_PADDING / 2;
+const MIN_ROW_GAP: u32 = 60;
+const LABEL_FONT_SIZE: u32 = 28;
+const LABEL_PADDING: u32 = 6;
+
+const LINE_COLOR: Color = Color::BLACK;
+const HIGHLIGHT_COLOR: Color = Color::rgb(255, 240, 160);
+/// Header fills with `--theme=color`, one per package.
+const PACKAGE_COLORS: [Color; 6] = [
+    Color::rgb(205, 225, 250),
+    Color::rgb(210, 240, 210),
+    Color::rgb(250, 225, 200),
+    Color::rgb(230, 215, 245),
+    Color::rgb(245, 210, 220),
+    Color::rgb(215, 240, 240),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NestingStyle {
+    /// Nested classes get their own box, connected to the outer class by a circle-plus.
+    Connector,
+    /// Nested classes are drawn inside the box of their outer class.
+    Inside,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutStyle {
+    /// Rows with the supertypes above their subtypes.
+    Layered,
+    /// Every class in a grid, the relations are routed between them.
+    Grid,
+}
+
+pub struct DiagramOptions {
+    pub nesting: NestingStyle,
+    pub layout: LayoutStyle,
+    /// Simple names of the annotations shown as `<<stereotypes>>`, `*` selects all of them.
+    pub stereotype_annotations: Vec<String>,
+    /// Appends `{readOnly}` to final fields.
+    pub show_read_only: bool,
+    /// Leaves fields drawn as associations out of the attribute compartment.
+    pub fields_as_edges_only: bool,
+    /// Draws final fields as compositions and collections as aggregations instead of associations.
+    pub infer_ownership: bool,
+    /// Fills the headers by package and draws each kind of relation in its own color.
+    pub colored: bool,
+    /// Simple or qualified names of the classes filled with the highlight color.
+    pub highlighted_classes: Vec<String>,
+}
+
+impl DiagramOptions {
+    pub fn new() -> Self {
+        Self {
+            nesting: NestingStyle::Connector,
+            layout: LayoutStyle::Layered,
+            stereotype_annotations: Vec::new(),
+            show_read_only: false,
+            fields_as_edges_only: false,
+            infer_ownership: false,
+            colored: false,
+            highlighted_classes: Vec::new(),
+        }
+    }
+
+    pub fn is_highlighted(&self, class: &Class) -> bool {
+        self.highlighted_classes.iter().any(|name| *name == class.name || *name == class.qualified_name())
+    }
+
+    pub fn shows_annotation(&self, annotation: &Annotation) -> bool {
+        self.stereotype_annotations.iter().any(|a| a == "*" || a == annotation.simple_name())
+    }
+}
+
+/// Width of the dashed template parameter box, 0 for classes without type parameters.
+fn measure_template<C: Canvas>(class: &Class, fonts: &Fonts) -> u32 {
+    if class.type_parameters.is_empty() {
+        0
+    } else {
+        let text = type_parameters_to_string(&class.type_parameters);
+        C::measure_text(&text, TEMPLATE_FONT_SIZE as usize, fonts, TextStyle::plain()) + TEMPLATE_PADDING * 2
+    }
+}
+
+fn field_row(field: &Field, options: &DiagramOptions) -> (String, TextStyle) {
+    let mut text = with_stereotypes(field.to_string(), &field.annotations, options);
+
+    if options.show_read_only && field.modifiers.is_final {
+        text.push_str(" {readOnly}");
+    }
+
+    (text, TextStyle::new(false, false, field.modifiers.is_static))
+}
+
+/// Static members are underlined and abstract methods are italic.
+fn method_row(method: &Method, options: &DiagramOptions) -> (String, TextStyle) {
+    (with_stereotypes(method.to_string(), &method.annotations, options),
+     TextStyle::new(false, method.modifiers.is_abstract, method.modifiers.is_static))
+}
+
+/// Text rows of every compartment below the heading.
+fn class_compartments(class: &Class, options: &DiagramOptions) -> Vec<Vec<(String, TextStyle)>> {
+    let plain = TextStyle::plain();
+    let mut compartments = Vec::new();
+
+    if class.kind == ClassKind::Enum {
+        compartments.push(class.enum_constants.iter()
+                               .map(|c| (with_stereotypes(c.to_string(), &c.annotations, options), plain))
+                               .collect());
+    }
+
+    compartments.push(class.record_components.iter()
+                           .map(|c| (with_stereotypes(c.to_string(), &c.annotations, options), plain))
+                           .chain(class.fields.iter()
+                                       .filter(|f| !options.fields_as_edges_only || field_targets(f).is_empty())
+                                       .map(|f| field_row(f, options)))
+                           .collect());
+    compartments.push(class.methods.iter()
+                           .map(|m| method_row(m, options))
+                           .collect());
+
+    compartments
+}
+
+/// Names of the annotations that are shown as stereotypes.
+pub fn annotation_stereotypes(annotations: &[Annotation], options: &DiagramOptions) -> Vec<String> {
+    annotations.iter()
+               .filter(|a| options.shows_annotation(a))
+               .map(|a| a.simple_name().to_string())
+               .collect()
+}
+
+fn with_stereotypes(text: String, annotations: &[Annotation], options: &DiagramOptions) -> String {
+    let stereotypes = annotation_stereotypes(annotations, options);
+
+    if stereotypes.is_empty() {
+        text
+    } else {
+        format!("<<{}>> {}", stereotypes.join(", "), text)
+    }
+}
+
+fn stereotype_text(class: &Class, options: &DiagramOptions) -> Option<String> {
+    let mut stereotypes = annotation_stereotypes(&class.annotations, options);
+
+    if let Some(stereotype) = class.kind.stereotype() {
+        stereotypes.insert(0, stereotype.to_string());
+    }
+
+    if stereotypes.is_empty() {
+        None
+    } else {
+        Some(format!("<<{}>>", stereotypes.join(", ")))
+    }
+}
+
+/// Class names are bold, and italic for abstract classes.
+fn heading_style(class: &Class) -> TextStyle {
+    TextStyle::new(true, class.modifiers.is_abstract, false)
+}
+
+/// Header fill of the classes of a package, the same on every run.
+fn package_color(class: &Class) -> Color {
+    let package = class.package.as_deref().unwrap_or("");
+    let hash = package.bytes().fold(0usize, |hash, b| hash.wrapping_mul(31).wrapping_add(b as usize));
+
+    PACKAGE_COLORS[hash % PACKAGE_COLORS.len()]
+}
+
+fn relation_color(kind: RelationKind, options: &DiagramOptions) -> Color {
+    if !options.colored {
+        return LINE_COLOR;
+    }
+
+    match kind {
+        RelationKind::Generalization | RelationKind::Realization => Color::rgb(30, 80, 160),
+        RelationKind::Association | RelationKind::Aggregation | RelationKind::Composition => Color::rgb(30, 120, 60),
+        RelationKind::Dependency => Color::rgb(170, 90, 20),
+        RelationKind::Nesting => Color::rgb(90, 90, 90),
+    }
+}
+
+fn heading_height(class: &Class, options: &DiagramOptions) -> u32 {
+    if stereotype_text(class, options).is_some() {
+        HEADING_HEIGHT + STEREOTYPE_HEIGHT
+    } else {
+        HEADING_HEIGHT
+    }
+}
+
+/// Size of the compartment that holds nested class boxes drawn inside their outer class.
+fn measure_nested<C: Canvas>(class: &Class, fonts: &Fonts, options: &DiagramOptions) -> (u32, u32) {
+    if options.nesting != NestingStyle::Inside || class.nested.is_empty() {
+        return (0, 0);
+    }
+
+    let sizes = class.nested.iter().map(|c| measure_class::<C>(c, fonts, options).size()).collect::<Vec<_>>();
+    let width = sizes.iter().map(|(w, _)| w + UML_PADDING).sum::<u32>() + UML_PADDING;
+    let height = sizes.iter().map(|(_, h)| *h).max().unwrap_or(0) + UML_PADDING * 2;
+
+    (width, height)
+}
+
+/// Everything about the box of a class that depends on measuring text, so layout and drawing agree.
+struct ClassMetrics {
+    compartments: Vec<Vec<(String, TextStyle)>>,
+    stereotype: Option<String>,
+    stereotype_width: u32,
+    name_width: u32,
+    /// Size of the class box itself, without the template box sticking out.
+    uml_width: u32,
+    uml_height: u32,
+    nested_height: u32,
+    template_width: u32,
+}
+
+impl ClassMetrics {
+    /// Size of the bounding box, including the template box.
+    fn size(&self) -> (u32, u32) {
+        if self.template_width > 0 {
+            (self.uml_width + TEMPLATE_OVERHANG, self.uml_height + TEMPLATE_HEIGHT / 2)
+        } else {
+            (self.uml_width, self.uml_height)
+        }
+    }
+}
+
+fn measure_class<C: Canvas>(class: &Class, fonts: &Fonts, options: &DiagramOptions) -> ClassMetrics {
+    let compartments = class_compartments(class, options);
+    let measure = |text: &str, style| C::measure_text(text, FONT_SIZE as usize, fonts, style);
+
+    let max_text_width = compartments.iter()
+                                     .flatten()
+                                     .map(|(t, style)| measure(t, *style))
+                                     .max().unwrap_or(0);
+
+    let name_width = C::measure_text(&class.name, HEADING_FONT_SIZE as usize, fonts, heading_style(class));
+    let stereotype = stereotype_text(class, options);
+    let stereotype_width = stereotype.as_ref().map_or(0, |s| measure(s, TextStyle::plain()));
+
+    let uml_width = cmp::max(cmp::max(name_width, stereotype_width), max_text_width) + UML_PADDING * 2;
+    let item_count = compartments.iter().map(|c| c.len()).sum::<usize>() as u32;
+    let uml_height = heading_height(class, options) + item_count * UML_ITEM_HEIGHT;
+
+    let (nested_width, nested_height) = measure_nested::<C>(class, fonts, options);
+    let template_width = measure_template::<C>(class, fonts);
+
+    ClassMetrics {
+        compartments,
+        stereotype,
+        stereotype_width,
+        name_width,
+        uml_width: cmp::max(cmp::max(uml_width, nested_width), template_width),
+        uml_height: uml_height + nested_height,
+        nested_height,
+        template_width,
+    }
+}
+
+/// Draws `class` and records the box of it and of every nested class drawn inside of it in `rects`,
+/// in the order of `Class::flatten`. The template box is left out, edges end at the class box.
+fn render_class<C: Canvas>(canvas: &mut C, class: &Class, start_x: u32, start_y: u32, fonts: &Fonts,
+                           options: &DiagramOptions, rects: &mut Vec<Rect>) {
+    let metrics = measure_class::<C>(class, fonts, options);
+    let uml_width = metrics.uml_width;
+
+    // The template box overlaps the upper right corner, so the class box moves down by half of it
+    let start_y = if metrics.template_width > 0 { start_y + TEMPLATE_HEIGHT / 2 } else { start_y };
+    rects.push(Rect::new(start_x, start_y, uml_width, metrics.uml_height));
+
+    if options.is_highlighted(class) {
+        canvas.draw_filled_rect(start_x, start_y, uml_width, metrics.uml_height, HIGHLIGHT_COLOR);
+    } else if options.colored {
+        canvas.draw_filled_rect(start_x, start_y, uml_width, heading_height(class, options), package_color(class));
+    }
+    canvas.draw_rect(start_x, start_y, uml_width, metrics.uml_height, LINE_COLOR);
+
+    let mut name_y = start_y + UML_PADDING;
+    if let Some(stereotype) = &metrics.stereotype {
+        canvas.draw_text(start_x + uml_width / 2 - metrics.stereotype_width / 2, name_y,
+                         stereotype, FONT_SIZE as usize, fonts, TextStyle::plain());
+        name_y += STEREOTYPE_HEIGHT;
+    }
+
+    canvas.draw_text(start_x + uml_width / 2 - metrics.name_width / 2, name_y,
+                     &class.name, HEADING_FONT_SIZE as usize, fonts, heading_style(class));
+
+    let mut compartment_y = start_y + heading_height(class, options);
+
+    for compartment in &metrics.compartments {
+        canvas.draw_line(start_x as i32, compartment_y as i32, (start_x + uml_width) as i32, compartment_y as i32,
+                         Dash::SOLID, LINE_COLOR);
+
+        for (i, (text, style)) in compartment.iter().enumerate() {
+            canvas.draw_text(start_x + UML_PADDING, compartment_y + (i as u32) * UML_ITEM_HEIGHT + UML_PADDING,
+                             text, FONT_SIZE as usize, fonts, *style);
+        }
+
+        compartment_y += compartment.len() as u32 * UML_ITEM_HEIGHT;
+    }
+
+    if metrics.nested_height > 0 {
+        canvas.draw_line(start_x as i32, compartment_y as i32, (start_x + uml_width) as i32, compartment_y as i32,
+                         Dash::SOLID, LINE_COLOR);
+        let mut nested_x = start_x + UML_PADDING;
+
+        for nested in &class.nested {
+            render_class(canvas, nested, nested_x, compartment_y + UML_PADDING, fonts, options, rects);
+            nested_x += measure_class::<C>(nested, fonts, options).size().0 + UML_PADDING;
+        }
+    }
+
+    if metrics.template_width > 0 {
+        let template_width = metrics.template_width;
+        let x1 = start_x + uml_width + TEMPLATE_OVERHANG - template_width;
+        let y1 = start_y - TEMPLATE_HEIGHT / 2;
+        let (x2, y2) = (x1 + template_width, y1 + TEMPLATE_HEIGHT);
+
+        canvas.draw_filled_rect(x1, y1, template_width, TEMPLATE_HEIGHT, Color::WHITE);
+        canvas.draw_polyline(&[(x1 as i32, y1 as i32), (x2 as i32, y1 as i32), (x2 as i32, y2 as i32),
+                               (x1 as i32, y2 as i32), (x1 as i32, y1 as i32)], DASHED, LINE_COLOR);
+        canvas.draw_text(x1 + TEMPLATE_PADDING, y1 + TEMPLATE_PADDING,
+                         &type_parameters_to_string(&class.type_parameters), TEMPLATE_FONT_SIZE as usize, fonts,
+                         TextStyle::plain());
+    }
+}
+
+/// The UML nesting connector, a circle with a cross inside at the end of the outer class.
+fn render_circle_plus<C: Canvas>(canvas: &mut C, tip: (i32, i32), tail: (i32, i32), color: Color) {
+    let radius = ARROW_SIZE / 2;
+    let (cx, cy) = ((tip.0 + tail.0) / 2, (tip.1 + tail.1) / 2);
+
+    canvas.draw_circle(cx, cy, radius, color);
+    canvas.draw_line(cx - radius, cy, cx + radius, cy, Dash::SOLID, color);
+    canvas.draw_line(cx, cy - radius, cx, cy + radius, Dash::SOLID, color);
+}
+
+/// Writes the role and the multiplicity on either side of the line entering the target at `tip`.
+/// Each label takes the first spot along the line that overlaps neither a box nor an earlier label.
+fn render_end_labels<C: Canvas>(canvas: &mut C, tip: (i32, i32), direction: (i32, i32), relation: &Relation,
+                                fonts: &Fonts, color: Color, occupied: &mut Vec<Rect>) {
+    const ATTEMPTS: i32 = 4;
+
+    let size = LABEL_FONT_SIZE as i32;
+    let padding = LABEL_PADDING as i32;
+    // Keep clear of the arrowhead
+    let offset = ARROW_SIZE / 2 + padding;
+
+    let labels = [(relation.role.as_deref().unwrap_or(""), 1), (relation.multiplicity.as_deref().unwrap_or(""), -1)];
+
+    for (text, preferred_side) in labels.iter() {
+        let width = C::measure_text(text, LABEL_FONT_SIZE as usize, fonts, TextStyle::plain()) as i32;
+
+        let candidates = (0..ATTEMPTS).flat_map(|step| vec![(step, *preferred_side), (step, -preferred_side)])
+                                      .map(|(step, side)| {
+            let along = padding + step * (size + padding);
+
+            let (x, y) = if direction.0 == 0 {
+                let y = if direction.1 < 0 { tip.1 + along } else { tip.1 - along - size };
+                (if side > 0 { tip.0 + offset } else { tip.0 - offset - width }, y)
+            } else {
+                let x = if direction.0 > 0 { tip.0 - along - width } else { tip.0 + along };
+                (x, if side > 0 { tip.1 - offset - size } else { tip.1 + offset })
+            };
+
+            Rect::new(cmp::max(x, 0) as u32, cmp::max(y, 0) as u32, width as u32, size as u32)
+        }).collect::<Vec<_>>();
+
+        let rect = *candidates.iter()
+                              .find(|c| !occupied.iter().any(|o| o.overlaps(c)))
+                              .unwrap_or(&candidates[0]);
+
+        canvas.draw_text(rect.x, rect.y, text, LABEL_FONT_SIZE as usize, fonts, TextStyle::plain().with_color(color));
+        occupied.push(rect);
+    }
+}
+
+/// Draws an edge along `points`, the last point being the tip of the arrow.
+fn render_relation<C: Canvas>(canvas: &mut C, points: &[(i32, i32)], relation: &Relation, fonts: &Fonts,
+                              options: &DiagramOptions, occupied: &mut Vec<Rect>) {
+    let color = relation_color(relation.kind, options);
+    let dash = match relation.kind {
+        RelationKind::Realization | RelationKind::Dependency => DASHED,
+        _ => Dash::SOLID,
+    };
+
+    let (tip, tail) = (points[points.len() - 1], points[points.len() - 2]);
+    let (dx, dy) = ((tip.0 - tail.0).signum(), (tip.1 - tail.1).signum());
+    let arrow_end = (tip.0 - dx * ARROW_SIZE, tip.1 - dy * ARROW_SIZE);
+
+    let (start, next) = (points[0], points[1]);
+    let (sx, sy) = ((next.0 - start.0).signum(), (next.1 - start.1).signum());
+    let diamond_end = (start.0 + sx * ARROW_SIZE, start.1 + sy * ARROW_SIZE);
+
+    let mut line = points.to_vec();
+    match relation.kind {
+        RelationKind::Association | RelationKind::Dependency => (),
+        RelationKind::Aggregation | RelationKind::Composition => line[0] = diamond_end,
+        _ => *line.last_mut().unwrap() = arrow_end,
+    }
+    canvas.draw_polyline(&line, dash, color);
+
+    match relation.kind {
+        RelationKind::Generalization | RelationKind::Realization =>
+            canvas.draw_arrowhead(tip, arrow_end, ArrowHead::HollowTriangle, ARROW_SIZE, color),
+        RelationKind::Nesting => render_circle_plus(canvas, tip, arrow_end, color),
+        RelationKind::Association | RelationKind::Dependency =>
+            canvas.draw_arrowhead(tip, arrow_end, ArrowHead::Open, ARROW_SIZE, color),
+        RelationKind::Aggregation => canvas.draw_arrowhead(start, diamond_end, ArrowHead::HollowDiamond, ARROW_SIZE, color),
+        RelationKind::Composition => canvas.draw_arrowhead(start, diamond_end, ArrowHead::FilledDiamond, ARROW_SIZE, color),
+    }
+
+    if relation.role.is_some() {
+        render_end_labels(canvas, tip, (dx, dy), relation, fonts, color, occupied);
+    }
+}
+
+pub fn render_diagram<C: Canvas>(classes: &[&Class], fonts: &Fonts, options: &DiagramOptions) -> C {
+    // Room for edges routed around the outermost boxes
+    const MARGIN: u32 = 60;
+
+    let all_classes = classes.iter().flat_map(|c| c.flatten()).collect::<Vec<_>>();
+    let boxes = match options.nesting {
+        NestingStyle::Connector => all_classes.clone(),
+        NestingStyle::Inside => classes.to_vec(),
+    };
+
+    let relations = all_relations(&all_classes, options.nesting == NestingStyle::Connector, options.infer_ownership);
+
+    // Classes drawn inside of their outer class are placed together with it
+    let mut box_of = Vec::new();
+    for (i, class) in boxes.iter().enumerate() {
+        match options.nesting {
+            NestingStyle::Connector => box_of.push(i),
+            NestingStyle::Inside => box_of.extend(class.flatten().iter().map(|_| i)),
+        }
+    }
+
+    let sizes = boxes.iter().map(|c| measure_class::<C>(c, fonts, options).size()).collect::<Vec<_>>();
+    // Only the hierarchy decides the rows, the other relations are routed wherever their classes end up
+    let edges = relations.iter()
+                         .filter(|r| matches!(r.kind, RelationKind::Generalization | RelationKind::Realization |
+                                                      RelationKind::Nesting))
+                         .map(|r| (box_of[r.from], box_of[r.to]))
+                         .collect::<Vec<_>>();
+    // Labels at the ends of associations need room between the rows
+    let min_gap = if relations.iter().any(|r| r.role.is_some()) {
+        MIN_ROW_GAP + 2 * (LABEL_FONT_SIZE + LABEL_PADDING)
+    } else {
+        MIN_ROW_GAP
+    };
+    let layout = match options.layout {
+        LayoutStyle::Layered => layout::layout(&sizes, &edges, min_gap),
+        LayoutStyle::Grid => layout::layout(&sizes, &[], min_gap),
+    };
+
+    let (width, height) = (layout.width + MARGIN * 2, layout.height + MARGIN * 2);
+    let mut canvas = C::with_size(width, height);
+    let mut rects = Vec::new();
+
+    for (class, (x, y)) in boxes.iter().zip(&layout.positions) {
+        render_class(&mut canvas, class, x + MARGIN, y + MARGIN, fonts, options, &mut rects);
+    }
+
+    let class_edges = relations.iter().map(|r| (r.from, r.to)).collect::<Vec<_>>();
+    let routes = route::route_edges(&rects, &class_edges, width, height);
+
+    // Labels of the edges must not cover the boxes or each other
+    let mut occupied = rects.clone();
+    for (relation, points) in relations.iter().zip(&routes) {
+        render_relation(&mut canvas, points, relation, fonts, options, &mut occupied);
+    }
+
+    canvas
+}
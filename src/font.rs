@@ -0,0 +1,323 @@
+use freetype::bitmap::PixelMode;
+use freetype::face::{self, KerningMode};
+use freetype::{Library, Matrix, Vector};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::iter;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::canvas::TextStyle;
+
+/// Suffixes of the file names of the bold, italic and bold italic variants of a font,
+/// like `DejaVuSans-Bold.ttf` next to `DejaVuSans.ttf` or `arialbd.ttf` next to `arial.ttf`.
+const BOLD_SUFFIXES: &[&str] = &["-Bold", "Bold", "-bold", "bd", "b"];
+const ITALIC_SUFFIXES: &[&str] = &["-Italic", "-Oblique", "Italic", "-italic", "i"];
+const BOLD_ITALIC_SUFFIXES: &[&str] = &["-BoldItalic", "-BoldOblique", "BoldItalic", "-bolditalic", "bi", "z"];
+
+/// Fonts with a wide coverage, CJK in particular, tried for characters the diagram font lacks.
+const SYSTEM_FALLBACKS: &[&str] = &[
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/wenquanyi/wqy-microhei/wqy-microhei.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "C:\\Windows\\Fonts\\meiryo.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+];
+
+/// DejaVu Sans and DejaVu Sans Mono, built into the binary so no font has to be installed.
+/// See `fonts/LICENSE` for their license.
+const BUILTIN_SANS: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+const BUILTIN_SANS_BOLD: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+const BUILTIN_MONO: &[u8] = include_bytes!("../fonts/DejaVuSansMono.ttf");
+const BUILTIN_MONO_BOLD: &[u8] = include_bytes!("../fonts/DejaVuSansMono-Bold.ttf");
+
+/// Slant of synthesized italics in 16.16 fixed point, about 12 degrees.
+const ITALIC_SHEAR: freetype::freetype_sys::FT_Fixed = 0x3600;
+
+/// The fonts built into the binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinFont {
+    Sans,
+    Mono,
+}
+
+impl BuiltinFont {
+    /// The built-in font called `name` on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sans" => Some(BuiltinFont::Sans),
+            "mono" => Some(BuiltinFont::Mono),
+            _ => None,
+        }
+    }
+}
+
+/// A rendered glyph, kept so every character is only rasterized once per size and style.
+pub struct Glyph {
+    /// Offset of the bitmap from the pen position, `top` upwards from the baseline.
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub rows: i32,
+    /// Coverage of every pixel of the bitmap, row by row.
+    pub coverage: Vec<u8>,
+    pub advance: i32,
+    /// Position of the face in the chain of the style and its glyph index there, for kerning.
+    face: usize,
+    index: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    c: char,
+    font_size: usize,
+    bold: bool,
+    italic: bool,
+}
+
+impl GlyphKey {
+    fn new(c: char, font_size: usize, style: TextStyle) -> Self {
+        Self {
+            c,
+            font_size,
+            bold: style.bold,
+            italic: style.italic,
+        }
+    }
+}
+
+/// The font faces text is drawn with. Styles without a face of their own are synthesized
+/// from the closest face there is.
+pub struct Fonts {
+    pub regular: face::Face,
+    pub bold: Option<face::Face>,
+    pub italic: Option<face::Face>,
+    pub bold_italic: Option<face::Face>,
+    /// Faces tried in order for characters the face of a style has no glyph for.
+    pub fallbacks: Vec<face::Face>,
+    glyphs: RefCell<HashMap<GlyphKey, Rc<Glyph>>>,
+    kernings: RefCell<HashMap<(GlyphKey, char), i32>>,
+}
+
+impl Fonts {
+    pub fn new(regular: face::Face) -> Self {
+        Self {
+            regular,
+            bold: None,
+            italic: None,
+            bold_italic: None,
+            fallbacks: Vec::new(),
+            glyphs: RefCell::new(HashMap::new()),
+            kernings: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Loads the regular face from `path` together with the variants found next to it,
+    /// and the system fonts that are installed as fallbacks.
+    pub fn load(library: &Library, path: &str) -> freetype::FtResult<Self> {
+        let mut fonts = Self::new(library.new_face(path, 0)?);
+
+        fonts.bold = load_variant(library, path, BOLD_SUFFIXES);
+        fonts.italic = load_variant(library, path, ITALIC_SUFFIXES);
+        fonts.bold_italic = load_variant(library, path, BOLD_ITALIC_SUFFIXES);
+        fonts.fallbacks = load_system_fallbacks(library);
+
+        Ok(fonts)
+    }
+
+    /// Loads one of the fonts built into the binary. Italics are synthesized.
+    pub fn builtin(library: &Library, font: BuiltinFont) -> freetype::FtResult<Self> {
+        let (regular, bold) = match font {
+            BuiltinFont::Sans => (BUILTIN_SANS, BUILTIN_SANS_BOLD),
+            BuiltinFont::Mono => (BUILTIN_MONO, BUILTIN_MONO_BOLD),
+        };
+
+        let mut fonts = Self::new(library.new_memory_face(regular.to_vec(), 0)?);
+        fonts.bold = Some(library.new_memory_face(bold.to_vec(), 0)?);
+        fonts.fallbacks = load_system_fallbacks(library);
+
+        Ok(fonts)
+    }
+
+    /// The face to draw `style` with, followed by whether bold and italic still have to be synthesized.
+    pub fn face(&self, style: TextStyle) -> (&face::Face, bool, bool) {
+        match (style.bold, style.italic) {
+            (true, true) => match (&self.bold_italic, &self.bold, &self.italic) {
+                (Some(face), _, _) => (face, false, false),
+                (None, Some(face), _) => (face, false, true),
+                (None, None, Some(face)) => (face, true, false),
+                (None, None, None) => (&self.regular, true, true),
+            },
+            (true, false) => match &self.bold {
+                Some(face) => (face, false, false),
+                None => (&self.regular, true, false),
+            },
+            (false, true) => match &self.italic {
+                Some(face) => (face, false, false),
+                None => (&self.regular, false, true),
+            },
+            (false, false) => (&self.regular, false, false),
+        }
+    }
+
+    /// The face of `style` followed by the fallbacks.
+    fn chain(&self, style: TextStyle) -> impl Iterator<Item = &face::Face> {
+        iter::once(self.face(style).0).chain(self.fallbacks.iter())
+    }
+
+    /// The glyphs of `text` with their pen positions, kerned against their neighbours,
+    /// followed by the advance of the whole text.
+    pub fn shape(&self, text: &str, font_size: usize, style: TextStyle) -> (Vec<(i32, Rc<Glyph>)>, i32) {
+        let mut glyphs = Vec::new();
+        let mut pen_x = 0;
+        let mut previous: Option<(char, Rc<Glyph>)> = None;
+
+        for c in text.chars() {
+            let glyph = self.glyph(c, font_size, style);
+
+            if let Some((previous_c, previous)) = &previous {
+                pen_x += self.kerning(*previous_c, previous, c, &glyph, font_size, style);
+            }
+
+            glyphs.push((pen_x, glyph.clone()));
+            pen_x += glyph.advance;
+            previous = Some((c, glyph));
+        }
+
+        (glyphs, pen_x)
+    }
+
+    fn glyph(&self, c: char, font_size: usize, style: TextStyle) -> Rc<Glyph> {
+        let key = GlyphKey::new(c, font_size, style);
+
+        if let Some(glyph) = self.glyphs.borrow().get(&key) {
+            return glyph.clone();
+        }
+
+        let glyph = Rc::new(self.rasterize(c, font_size, style));
+        self.glyphs.borrow_mut().insert(key, glyph.clone());
+        glyph
+    }
+
+    /// Renders `c` with the first face of the chain that has it. Characters no face has
+    /// are drawn with the missing glyph box of the styled face, and glyphs that fail to load stay empty.
+    fn rasterize(&self, c: char, font_size: usize, style: TextStyle) -> Glyph {
+        let (styled, _, fake_italic) = self.face(style);
+        let (face_id, font, index) = self.chain(style)
+                                         .enumerate()
+                                         .map(|(i, face)| (i, face, face.get_char_index(c as usize)))
+                                         .find(|(_, _, index)| *index != 0)
+                                         .unwrap_or((0, styled, 0));
+
+        let mut glyph = Glyph {
+            left: 0,
+            top: 0,
+            width: 0,
+            rows: 0,
+            coverage: Vec::new(),
+            advance: 0,
+            face: face_id,
+            index,
+        };
+
+        if font.set_char_size(0, font_size as isize * 64, 0, 0).is_err() {
+            return glyph;
+        }
+
+        if fake_italic {
+            let mut matrix = Matrix { xx: 0x10000, xy: ITALIC_SHEAR, yx: 0, yy: 0x10000 };
+            font.set_transform(&mut matrix, &mut Vector { x: 0, y: 0 });
+        }
+
+        let loaded = font.load_glyph(index, face::LoadFlag::RENDER);
+
+        if fake_italic {
+            let mut identity = Matrix { xx: 0x10000, xy: 0, yx: 0, yy: 0x10000 };
+            font.set_transform(&mut identity, &mut Vector { x: 0, y: 0 });
+        }
+
+        if loaded.is_err() {
+            return glyph;
+        }
+
+        let slot = font.glyph();
+        let bitmap = slot.bitmap();
+        glyph.advance = (slot.advance().x >> 6) as i32;
+        glyph.left = slot.bitmap_left();
+        glyph.top = slot.bitmap_top();
+
+        // Glyphs without ink, like spaces, have no buffer at all
+        if bitmap.width() == 0 || bitmap.rows() == 0 {
+            return glyph;
+        }
+
+        let (width, rows, pitch) = (bitmap.width(), bitmap.rows(), bitmap.pitch().abs());
+        let buffer = bitmap.buffer();
+        let coverage = match bitmap.pixel_mode() {
+            Ok(PixelMode::Gray) => (0..rows).flat_map(|y| (0..width).map(move |x| (x, y)))
+                                            .map(|(x, y)| buffer[(x + y * pitch) as usize])
+                                            .collect(),
+            Ok(PixelMode::Mono) => (0..rows).flat_map(|y| (0..width).map(move |x| (x, y)))
+                                            .map(|(x, y)| (buffer[(x / 8 + y * pitch) as usize] << (x % 8)) & 0x80)
+                                            .map(|bit| if bit != 0 { 255 } else { 0 })
+                                            .collect(),
+            // Color and LCD bitmaps are left out, the pen still advances
+            _ => return glyph,
+        };
+
+        glyph.width = width;
+        glyph.rows = rows;
+        glyph.coverage = coverage;
+        glyph
+    }
+
+    /// Adjustment of the pen between two neighbouring glyphs, as long as both come from the same face.
+    fn kerning(&self, left_c: char, left: &Glyph, right_c: char, right: &Glyph, font_size: usize,
+               style: TextStyle) -> i32 {
+        if left.face != right.face || left.index == 0 || right.index == 0 {
+            return 0;
+        }
+
+        let key = (GlyphKey::new(left_c, font_size, style), right_c);
+        if let Some(kerning) = self.kernings.borrow().get(&key) {
+            return *kerning;
+        }
+
+        let kerning = match self.chain(style).nth(left.face) {
+            Some(font) if font.has_kerning() => {
+                font.set_char_size(0, font_size as isize * 64, 0, 0)
+                    .and_then(|_| font.get_kerning(left.index, right.index, KerningMode::KerningDefault))
+                    .map(|vector| (vector.x >> 6) as i32)
+                    .unwrap_or(0)
+            },
+            _ => 0,
+        };
+
+        self.kernings.borrow_mut().insert(key, kerning);
+        kerning
+    }
+}
+
+fn load_system_fallbacks(library: &Library) -> Vec<face::Face> {
+    SYSTEM_FALLBACKS.iter()
+                    .filter(|fallback| Path::new(fallback).is_file())
+                    .filter_map(|fallback| library.new_face(*fallback, 0).ok())
+                    .collect()
+}
+
+fn load_variant(library: &Library, path: &str, suffixes: &[&str]) -> Option<face::Face> {
+    let path = Path::new(path);
+    let stem = path.file_stem()?.to_str()?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("ttf");
+
+    suffixes.iter()
+            .map(|suffix| path.with_file_name(format!("{}{}.{}", stem, suffix, extension)))
+            .filter(|candidate| candidate.is_file())
+            .find_map(|candidate| library.new_face(candidate, 0).ok())
+}
@@ -1,9 +1,18 @@
-use freetype::face;
-use freetype::bitmap::PixelMode;
-use std::io;
+use std::cmp;
+use std::io::{self, Write};
+use std::rc::Rc;
 
+use crate::canvas::{Canvas, Color, Dash, TextStyle};
+use crate::font::{Fonts, Glyph};
+
+/// How far synthesized bold text is struck a second time to the right.
+fn fake_bold_offset(font_size: usize) -> u32 {
+    cmp::max(font_size as u32 / 20, 1)
+}
+
+/// An RGBA raster. Everything is alpha blended over what was drawn before.
 pub struct Image {
-    data: Vec<u8>,
+    data: Vec<Color>,
     width: usize,
     height: usize,
 }
@@ -14,121 +23,267 @@ impl Image {
         Self {
             width,
             height,
-            data: vec![255; width * height],
+            data: vec![Color::WHITE; width * height],
         }
     }
 
-    pub fn get(&self, x: u32, y: u32) -> u8 {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Color {
         let y = y as usize;
         let x = x as usize;
         if x < self.width && y < self.height {
             self.data[x + y * self.width]
         } else {
-            0
+            Color::BLACK
         }
     }
 
-    fn set(&mut self, x: u32, y: u32, val: u8) {
+    /// Paints `color` over the pixel, `coverage` being the share of the pixel it covers.
+    fn blend(&mut self, x: u32, y: u32, color: Color, coverage: u8) {
         let y = y as usize;
         let x = x as usize;
         if x < self.width && y < self.height {
-            self.data[x + y * self.width] = val;
+            let pixel = &mut self.data[x + y * self.width];
+            *pixel = color.blend_over(*pixel, coverage);
         }
     }
 
-    pub fn render_text(&mut self, x: u32, y: u32, text: &str, font_size: usize, font: &face::Face) {
-        
-        let mut pen_x = x as i32;
-        let mut pen_y = y as i32;
-
-        font.set_char_size(0, font_size as isize * 64, 0, 0)
-            .expect("Could not set the font size!");
-        
-
-        for c in text.chars() {
-            font.load_char(c as usize, face::LoadFlag::RENDER)
-                .expect(&format!("Could not load char {:?} in text {:?}!", c, text));
-
-            let glyph = font.glyph();
-            let bitmap = glyph.bitmap();
-
-            //TODO: support multiple pixel modes
-            assert!(bitmap.pixel_mode().unwrap() == PixelMode::Gray);
+    fn plot(&mut self, x: u32, y: u32, color: Color) {
+        self.blend(x, y, color, 255);
+    }
 
-            let char_start_x = pen_x + glyph.bitmap_left();
-            let char_start_y = pen_y + (font_size as i32 - glyph.bitmap_top());
+    /// Draws the glyphs of `text` as shaped by `fonts`, the baseline `font_size` below `y`.
+    fn render_glyphs(&mut self, x: u32, y: u32, glyphs: &[(i32, Rc<Glyph>)], font_size: usize, color: Color) {
+        for (pen_x, glyph) in glyphs {
+            let char_start_x = x as i32 + pen_x + glyph.left;
+            let char_start_y = y as i32 + font_size as i32 - glyph.top;
 
-            for y in 0..bitmap.rows() {
-                for x in 0..bitmap.width() {
-                    let grey_val = bitmap.buffer()[(x + y * bitmap.pitch()) as usize];
+            for y in 0..glyph.rows {
+                for x in 0..glyph.width {
+                    let coverage = glyph.coverage[(x + y * glyph.width) as usize];
                     let pos_x = char_start_x + x;
                     let pos_y = char_start_y + y;
 
-                    if 0 <= pos_x && pos_x < self.width as i32 && 0 <= pos_y && pos_y < self.height as i32 {
-                        self.set(pos_x as u32, pos_y as u32, 255 - grey_val);
+                    // Glyph bitmaps may overlap their neighbours, where they are empty
+                    if coverage > 0 && 0 <= pos_x && 0 <= pos_y {
+                        self.blend(pos_x as u32, pos_y as u32, color, coverage);
                     }
                 }
             }
-
-            pen_x += (glyph.advance().x >> 6) as i32;
-            pen_y += (glyph.advance().y >> 6) as i32;
         }
     }
 
-    pub fn measure_text(text: &str, font_size: usize, font: &face::Face) -> u32 {
-        let mut width: u32 = 0;
+    /// Renders text in the face of its style, with a line below the baseline if underlined.
+    /// Without a bold face the text is struck twice, without an italic face it is slanted by a shear transform.
+    pub fn render_styled_text(&mut self, x: u32, y: u32, text: &str, font_size: usize, fonts: &Fonts,
+                              style: TextStyle) {
+        let (_, fake_bold, _) = fonts.face(style);
+        let (glyphs, _) = fonts.shape(text, font_size, style);
 
-        font.set_char_size(0, font_size as isize * 64, 0, 0).expect("Could not set the font size!");
-
-        for c in text.chars() {
-            font.load_char(c as usize, face::LoadFlag::RENDER)
-                .expect(&format!("Could not load char {:?} in text {:?}!", c, text));
+        self.render_glyphs(x, y, &glyphs, font_size, style.color);
+        if fake_bold {
+            self.render_glyphs(x + fake_bold_offset(font_size), y, &glyphs, font_size, style.color);
+        }
 
-            let glyph = font.glyph();
-            width += (glyph.advance().x >> 6) as u32;
+        if style.underline {
+            let width = Self::measure_styled_text(text, font_size, fonts, style);
+            let underline_y = y + font_size as u32 + font_size as u32 / 8;
+            self.render_horizontal_line(underline_y, x, x + width, style.color);
         }
+    }
+
+    pub fn measure_styled_text(text: &str, font_size: usize, fonts: &Fonts, style: TextStyle) -> u32 {
+        let (_, fake_bold, _) = fonts.face(style);
+        let width = cmp::max(fonts.shape(text, font_size, style).1, 0) as u32;
 
-        width
+        if fake_bold { width + fake_bold_offset(font_size) } else { width }
     }
 
-    pub fn render_horizontal_line(&mut self, y: u32, x1: u32, x2: u32, grey_val: u8) {
+    pub fn render_horizontal_line(&mut self, y: u32, x1: u32, x2: u32, color: Color) {
         for x in x1..=x2 {
-            self.set(x, y, grey_val);
+            self.plot(x, y, color);
         }
     }
     
-    pub fn render_vertical_line(&mut self, x: u32, y1: u32, y2: u32, grey_val: u8) {
+    pub fn render_vertical_line(&mut self, x: u32, y1: u32, y2: u32, color: Color) {
         for y in y1..=y2 {
-            self.set(x, y, grey_val);
+            self.plot(x, y, color);
+        }
+    }
+
+    /// Anti-aliased line after Xiaolin Wu, at any angle. Every step along the major axis covers
+    /// the two pixels closest to the line, each by how close it is.
+    /// Only the pixels `dash` covers are drawn, starting `phase` pixels into the pattern;
+    /// the returned phase continues the pattern on the next segment.
+    pub fn render_line(&mut self, from: (i32, i32), to: (i32, i32), dash: Dash, phase: u32, color: Color) -> u32 {
+        let steep = (to.1 - from.1).abs() > (to.0 - from.0).abs();
+        // Walk along the major axis `a`, the minor axis `b` follows the slope
+        let ((a1, b1), (a2, b2)) = if steep { ((from.1, from.0), (to.1, to.0)) } else { (from, to) };
+
+        let length = (a2 - a1).abs();
+        let step = if a1 <= a2 { 1 } else { -1 };
+        let gradient = if length == 0 { 0.0 } else { (b2 - b1) as f32 / length as f32 };
+
+        for i in 0..=length {
+            if !dash.covers(phase + i as u32) {
+                continue;
+            }
+
+            let a = a1 + i * step;
+            let b = b1 as f32 + gradient * i as f32;
+            let fraction = b - b.floor();
+
+            for (offset, share) in [(0, 1.0 - fraction), (1, fraction)].iter() {
+                let coverage = (share * 255.0).round() as u8;
+                let (x, y) = if steep { (b.floor() as i32 + offset, a) } else { (a, b.floor() as i32 + offset) };
+
+                if coverage > 0 && x >= 0 && y >= 0 {
+                    self.blend(x as u32, y as u32, color, coverage);
+                }
+            }
+        }
+
+        phase + length as u32
+    }
+
+    /// Anti-aliased circle outline around (`cx`, `cy`), covering pixels by their distance to the circle.
+    pub fn render_circle(&mut self, cx: i32, cy: i32, radius: i32, color: Color) {
+        for y in cy - radius - 1..=cy + radius + 1 {
+            for x in cx - radius - 1..=cx + radius + 1 {
+                let distance = (((x - cx) * (x - cx) + (y - cy) * (y - cy)) as f32).sqrt();
+                let coverage = ((1.0 - (distance - radius as f32).abs()) * 255.0).round();
+
+                if coverage > 0.0 && x >= 0 && y >= 0 {
+                    self.blend(x as u32, y as u32, color, coverage as u8);
+                }
+            }
+        }
+    }
+
+    pub fn render_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        self.render_horizontal_line(y,     x, x + w, color);
+        self.render_horizontal_line(y + h, x, x + w, color);
+
+        self.render_vertical_line(x, y, y + h, color);
+        self.render_vertical_line(x + w, y, y + h, color);
+    }
+
+    pub fn fill_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        for y in y..=y + h {
+            self.render_horizontal_line(y, x, x + w, color);
         }
     }
 
-    pub fn render_rect(&mut self, x: u32, y: u32, w: u32, h: u32, grey_val: u8) {
-        self.render_horizontal_line(y,     x, x + w, grey_val);
-        self.render_horizontal_line(y + h, x, x + w, grey_val);
+    /// Scanline fill of the polygon through `points`, sampled at pixel centers.
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        let min_y = points.iter().map(|p| p.1).min().unwrap_or(0).max(0);
+        let max_y = points.iter().map(|p| p.1).max().unwrap_or(0).min(self.height as i32 - 1);
+
+        for y in min_y..=max_y {
+            let center = y as f32 + 0.5;
+            let mut crossings = Vec::new();
+
+            for (i, &(x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(i + 1) % points.len()];
+                let (top, bottom) = (cmp::min(y1, y2) as f32, cmp::max(y1, y2) as f32);
+
+                if top <= center && center < bottom {
+                    crossings.push(x1 as f32 + (center - y1 as f32) * (x2 - x1) as f32 / (y2 - y1) as f32);
+                }
+            }
+
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+            for pair in crossings.chunks(2) {
+                if let [start, end] = pair {
+                    let start = cmp::max(start.round() as i32, 0);
+                    let end = cmp::min(end.round() as i32, self.width as i32 - 1);
 
-        self.render_vertical_line(x, y, y + h, grey_val);
-        self.render_vertical_line(x + w, y, y + h, grey_val);
+                    if start <= end {
+                        self.render_horizontal_line(y as u32, start as u32, end as u32, color);
+                    }
+                }
+            }
+        }
     }
 
-    pub fn save_to_file_ppm(&self, file_path: &str) -> io::Result<()> {
+    pub fn write_ppm<W: Write>(&self, mut out: W) -> io::Result<()> {
         let mut ppm_string = String::from("");
 
-        ppm_string.push_str("P2\n");
+        ppm_string.push_str("P3\n");
         ppm_string.push_str(&format!("{} {}\n", self.width, self.height));
         ppm_string.push_str(&format!("{}\n", 255));
 
         for y in 0..self.height {
             for x in 0..self.width {
-                ppm_string.push_str(&format!("{} ", self.get(x as u32, y as u32)));
+                let pixel = self.get(x as u32, y as u32);
+                ppm_string.push_str(&format!("{} {} {} ", pixel.r, pixel.g, pixel.b));
             }
             ppm_string.push('\n');
         }
 
-        use std::fs::File;
-        use std::io::Write;
-        let mut file = File::create(file_path)?;
-        file.write_all(ppm_string.as_bytes())?;
+        out.write_all(ppm_string.as_bytes())?;
         Ok(())
     }
+
+    /// Writes the image as an 8 bit RGBA PNG.
+    pub fn write_png<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        let bytes = self.data.iter().flat_map(|c| vec![c.r, c.g, c.b, c.a]).collect::<Vec<_>>();
+        writer.write_image_data(&bytes)?;
+        Ok(())
+    }
+}
+
+impl Canvas for Image {
+    fn with_size(width: u32, height: u32) -> Self {
+        Self::new(width as usize, height as usize)
+    }
+
+    fn measure_text(text: &str, font_size: usize, fonts: &Fonts, style: TextStyle) -> u32 {
+        Image::measure_styled_text(text, font_size, fonts, style)
+    }
+
+    fn draw_text(&mut self, x: u32, y: u32, text: &str, font_size: usize, fonts: &Fonts, style: TextStyle) {
+        self.render_styled_text(x, y, text, font_size, fonts, style);
+    }
+
+    fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, dash: Dash, color: Color) {
+        self.render_line((x1, y1), (x2, y2), dash, 0, color);
+    }
+
+    /// Continues the dash pattern around the bends.
+    fn draw_polyline(&mut self, points: &[(i32, i32)], dash: Dash, color: Color) {
+        let mut phase = 0;
+        for pair in points.windows(2) {
+            phase = self.render_line(pair[0], pair[1], dash, phase, color);
+        }
+    }
+
+    fn draw_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        self.render_rect(x, y, w, h, color);
+    }
+
+    fn draw_filled_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        self.fill_rect(x, y, w, h, color);
+    }
+
+    fn draw_circle(&mut self, cx: i32, cy: i32, radius: i32, color: Color) {
+        self.render_circle(cx, cy, radius, color);
+    }
+
+    fn fill_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        Image::fill_polygon(self, points, color);
+    }
 }
@@ -1,7 +1,97 @@
-#[derive(Debug)]
-pub struct Type(pub String);
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Type {
+    pub name: String,
+    pub arguments: Vec<TypeArgument>,
+    pub array_dimensions: usize,
+    /// Fully qualified name of the project class this type refers to, filled in by `resolve::resolve_types`.
+    pub qualified_name: Option<String>,
+}
+
+impl Type {
+    pub fn new(name: String, arguments: Vec<TypeArgument>, array_dimensions: usize) -> Self {
+        Self {
+            name, arguments, array_dimensions, qualified_name: None,
+        }
+    }
+
+    pub fn simple(name: String) -> Self {
+        Self::new(name, Vec::new(), 0)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if !self.arguments.is_empty() {
+            let arguments = self.arguments.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            write!(f, "<{}>", arguments.join(", "))?;
+        }
+
+        for _ in 0..self.array_dimensions {
+            write!(f, "[]")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeArgument {
+    Type(Type),
+    Wildcard(Option<WildcardBound>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WildcardBound {
+    Extends(Type),
+    Super(Type),
+}
+
+impl fmt::Display for TypeArgument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Type(t) => write!(f, "{}", t),
+            Self::Wildcard(None) => write!(f, "?"),
+            Self::Wildcard(Some(WildcardBound::Extends(t))) => write!(f, "? extends {}", t),
+            Self::Wildcard(Some(WildcardBound::Super(t))) => write!(f, "? super {}", t),
+        }
+    }
+}
+
+/// A declared type variable like the `T extends Comparable<? super T>` in `class Box<...>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub bounds: Vec<Type>,
+}
 
-#[derive(Debug, PartialEq, Eq)]
+impl TypeParameter {
+    pub fn new(name: String, bounds: Vec<Type>) -> Self {
+        Self {
+            name, bounds
+        }
+    }
+}
+
+impl fmt::Display for TypeParameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.bounds.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            let bounds = self.bounds.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+            write!(f, "{} extends {}", self.name, bounds.join(" & "))
+        }
+    }
+}
+
+pub fn type_parameters_to_string(type_parameters: &[TypeParameter]) -> String {
+    type_parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Capsulation {
     Public,
     Private,
@@ -10,8 +100,8 @@ pub enum Capsulation {
 }
 
 impl Capsulation {
-    fn to_char(&self) -> char {
-        match *self {
+    fn to_char(self) -> char {
+        match self {
             Self::Public => '+',
             Self::Private => '-',
             Self::Protected => '#',
@@ -20,106 +110,294 @@ impl Capsulation {
     }
 }
 
+/// Modifiers besides the visibility, which is kept as `Capsulation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub is_static: bool,
+    pub is_abstract: bool,
+    pub is_final: bool,
+    pub is_default: bool,
+    pub is_synchronized: bool,
+}
+
+impl Modifiers {
+    pub fn new() -> Self {
+        Self {
+            is_static: false,
+            is_abstract: false,
+            is_final: false,
+            is_default: false,
+            is_synchronized: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Field {
     pub name: String,
     pub field_type: Type,
     pub capsulation: Capsulation,
+    pub modifiers: Modifiers,
+    pub annotations: Vec<Annotation>,
 }
 
 impl Field {
     pub fn new(field_type: Type, name: String, capsulation: Capsulation) -> Self {
         Self {
-            name, field_type, capsulation
+            name, field_type, capsulation, modifiers: Modifiers::new(), annotations: Vec::new(),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        let Type(type_name) = &self.field_type;
-        format!("{} {}: {}", 
-                self.capsulation.to_char(),
-                self.name,
-                type_name)
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.capsulation.to_char(), self.name, self.field_type)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub name: String,
+    /// Raw source text between the parentheses, if there are any.
+    pub arguments: Option<String>,
+}
+
+impl Annotation {
+    pub fn new(name: String, arguments: Option<String>) -> Self {
+        Self {
+            name, arguments
+        }
+    }
+
+    /// The name without its package, `Entity` for `@javax.persistence.Entity`.
+    pub fn simple_name(&self) -> &str {
+        self.name.rsplit('.').next().unwrap_or(&self.name)
     }
 }
 
 #[derive(Debug)]
-pub struct Parameter(pub Type, pub String);
+pub struct Parameter {
+    pub name: String,
+    pub param_type: Type,
+    pub annotations: Vec<Annotation>,
+}
 
 impl Parameter {
-    fn to_string(&self) -> String {
-        let Parameter(Type(type_name), name) = self;
-        format!("{}: {}", name, type_name)
+    pub fn new(param_type: Type, name: String) -> Self {
+        Self {
+            name, param_type, annotations: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.param_type)
     }
 }
 
 #[derive(Debug)]
 pub struct Method {
     pub name: String,
+    pub type_parameters: Vec<TypeParameter>,
     pub return_type: Type,
     pub parameters: Vec<Parameter>,
     pub capsulation: Capsulation,
+    pub modifiers: Modifiers,
+    pub annotations: Vec<Annotation>,
+    /// Types the body refers to through `new`, static calls, local variables and casts.
+    pub body_types: Vec<Type>,
 }
 
 impl Method {
     pub fn is_constructor(&self) -> bool {
-        let Type(type_name) = &self.return_type;
-        type_name.len() == 0
+        self.return_type.name.is_empty()
     }
 
     pub fn new(name: String, return_type: Type, parameters: Vec<Parameter>, capsulation: Capsulation) -> Self {
         Self {
-            name, return_type, parameters, capsulation,
+            name, type_parameters: Vec::new(), return_type, parameters, capsulation, modifiers: Modifiers::new(),
+            annotations: Vec::new(), body_types: Vec::new(),
         }
     }
 
     pub fn new_constructor(name: String, parameters: Vec<Parameter>, capsulation: Capsulation) -> Self {
         Self {
-            name, return_type: Type("".to_string()), parameters, capsulation,
+            name, type_parameters: Vec::new(), return_type: Type::simple("".to_string()), parameters, capsulation,
+            modifiers: Modifiers::new(), annotations: Vec::new(), body_types: Vec::new(),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
-        let Type(return_type_name) = &self.return_type;
-        let mut out = format!("{} {}(", self.capsulation.to_char(), self.name);
-       
-        if self.parameters.len() > 0 {
-            for i in 0..self.parameters.len() - 1 {
-                out.push_str(&format!("{}, ", self.parameters[i].to_string()));
-            }
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.capsulation.to_char(), self.name)?;
 
-            out.push_str(&format!("{})", self.parameters.last().unwrap().to_string()));
-            if !self.is_constructor() {
-                out.push_str(&format!(": {}", return_type_name))
-            }
-        } else {
-            if self.is_constructor() {
-                out.push(')');
-            } else {
-                out.push_str(&format!("): {}", return_type_name));
-            }
+        if !self.type_parameters.is_empty() {
+            write!(f, "<{}>", type_parameters_to_string(&self.type_parameters))?;
         }
 
-        out
+        let parameters = self.parameters.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        write!(f, "({})", parameters.join(", "))?;
+
+        if !self.is_constructor() {
+            write!(f, ": {}", self.return_type)?;
+        }
+
+        Ok(())
     }
 }
 
 pub enum Declaration {
     Method(Method),
-    Field(Field)
+    /// Every field of a declaration like `int a, b;`.
+    Fields(Vec<Field>),
+    Class(Class),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClassKind {
+    Class,
+    Interface,
+    Enum,
+    Record,
+    Annotation,
+}
+
+impl ClassKind {
+    pub fn stereotype(&self) -> Option<&'static str> {
+        match *self {
+            Self::Class => None,
+            Self::Interface => Some("interface"),
+            Self::Enum => Some("enum"),
+            Self::Record => Some("record"),
+            Self::Annotation => Some("annotation"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EnumConstant {
+    pub name: String,
+    /// Raw source text of the constructor arguments, if there are any.
+    pub arguments: Option<String>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl EnumConstant {
+    pub fn new(name: String, arguments: Option<String>) -> Self {
+        Self {
+            name, arguments, annotations: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for EnumConstant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.arguments {
+            Some(arguments) => write!(f, "{}({})", self.name, arguments),
+            None => write!(f, "{}", self.name),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Class {
     pub name: String,
+    pub package: Option<String>,
+    pub kind: ClassKind,
+    pub modifiers: Modifiers,
+    pub annotations: Vec<Annotation>,
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: Option<Type>,
+    /// Implemented interfaces, or the extended ones if this is an interface itself.
+    pub interfaces: Vec<Type>,
+    pub enum_constants: Vec<EnumConstant>,
+    pub record_components: Vec<Parameter>,
     pub fields: Vec<Field>,
     pub methods: Vec<Method>,
+    /// Nested, inner, local and anonymous classes.
+    pub nested: Vec<Class>,
+    /// Types referred to by field initializers and initializer blocks, like the body types of a method.
+    pub initializer_types: Vec<Type>,
 }
 
 impl Class {
     pub fn new(name: String, fields: Vec<Field>, methods: Vec<Method>) -> Self {
         Self {
-            name, fields, methods
+            name,
+            package: None,
+            kind: ClassKind::Class,
+            modifiers: Modifiers::new(),
+            annotations: Vec::new(),
+            type_parameters: Vec::new(),
+            superclass: None,
+            interfaces: Vec::new(),
+            enum_constants: Vec::new(),
+            record_components: Vec::new(),
+            fields,
+            methods,
+            nested: Vec::new(),
+            initializer_types: Vec::new(),
+        }
+    }
+
+    /// The name including the package, e.g. `com.example.Outer.Inner`.
+    pub fn qualified_name(&self) -> String {
+        match &self.package {
+            Some(package) => format!("{}.{}", package, self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    /// This class followed by all of its nested classes, depth first.
+    pub fn flatten(&self) -> Vec<&Class> {
+        let mut classes = vec![self];
+
+        for nested in &self.nested {
+            classes.extend(nested.flatten());
+        }
+
+        classes
+    }
+}
+
+/// `import a.b.C;`, `import a.b.*;` or one of their `import static` variants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// The imported name without the trailing `.*`.
+    pub name: String,
+    pub is_static: bool,
+    pub is_wildcard: bool,
+}
+
+impl Import {
+    pub fn new(name: String, is_static: bool, is_wildcard: bool) -> Self {
+        Self {
+            name, is_static, is_wildcard
+        }
+    }
+
+    /// The last segment of the name, `C` for `import a.b.C;`.
+    pub fn simple_name(&self) -> &str {
+        self.name.rsplit('.').next().unwrap_or(&self.name)
+    }
+}
+
+/// The contents of a single source file.
+#[derive(Debug)]
+pub struct CompilationUnit {
+    pub package: Option<String>,
+    pub imports: Vec<Import>,
+    pub classes: Vec<Class>,
+}
+
+impl CompilationUnit {
+    pub fn new() -> Self {
+        Self {
+            package: None,
+            imports: Vec::new(),
+            classes: Vec::new(),
         }
     }
 }
@@ -0,0 +1,354 @@
+use std::cmp;
+
+/// Horizontal space between two boxes of the same row.
+const NODE_SPACING: u32 = 40;
+/// Vertical distance between edges running through the same gap.
+const LANE_SPACING: u32 = 20;
+const ORDERING_SWEEPS: usize = 8;
+const POSITIONING_SWEEPS: usize = 4;
+
+/// Placement of the boxes of a diagram.
+pub struct Layout {
+    /// Top left corner of every box.
+    pub positions: Vec<(u32, u32)>,
+    /// Row of every box, supertypes are in rows above their subtypes.
+    pub rows: Vec<usize>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Layout {
+    /// The gap an edge between two boxes runs through: right below the target if it is
+    /// further up, right above it if it is further down, and below both otherwise.
+    fn edge_gap(&self, from: usize, to: usize) -> usize {
+        let (from_row, to_row) = (self.rows[from], self.rows[to]);
+
+        if to_row < from_row {
+            to_row
+        } else if to_row > from_row {
+            to_row - 1
+        } else {
+            from_row
+        }
+    }
+}
+
+/// A box, or a placeholder keeping room for an edge that passes through a row.
+struct Node {
+    width: u32,
+    row: usize,
+    /// Index of the box, `None` for placeholders.
+    box_index: Option<usize>,
+}
+
+/// Nodes split into ordered rows, `links` connect nodes of neighbouring rows as (upper, lower).
+struct Graph {
+    nodes: Vec<Node>,
+    order: Vec<Vec<usize>>,
+    links: Vec<(usize, usize)>,
+}
+
+impl Graph {
+    fn add_node(&mut self, width: u32, row: usize, box_index: Option<usize>) -> usize {
+        while self.order.len() <= row {
+            self.order.push(Vec::new());
+        }
+
+        self.nodes.push(Node { width, row, box_index });
+        self.order[row].push(self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Index of every node within its row.
+    fn positions(&self) -> Vec<usize> {
+        let mut positions = vec![0; self.nodes.len()];
+
+        for row in &self.order {
+            for (i, node) in row.iter().enumerate() {
+                positions[*node] = i;
+            }
+        }
+
+        positions
+    }
+
+    fn count_crossings(&self) -> usize {
+        let positions = self.positions();
+        let mut crossings = 0;
+
+        for (i, (upper1, lower1)) in self.links.iter().enumerate() {
+            for (upper2, lower2) in &self.links[i + 1..] {
+                if self.nodes[*upper1].row != self.nodes[*upper2].row {
+                    continue;
+                }
+
+                let upper_order = positions[*upper1].cmp(&positions[*upper2]);
+                let lower_order = positions[*lower1].cmp(&positions[*lower2]);
+                if upper_order != cmp::Ordering::Equal && lower_order != cmp::Ordering::Equal && upper_order != lower_order {
+                    crossings += 1;
+                }
+            }
+        }
+
+        crossings
+    }
+
+    /// Nodes linked to `node` in the row above, or below if `upwards` is false.
+    fn neighbours(&self, node: usize, upwards: bool) -> Vec<usize> {
+        self.links.iter()
+                  .filter_map(|&(upper, lower)| {
+                      if upwards && lower == node {
+                          Some(upper)
+                      } else if !upwards && upper == node {
+                          Some(lower)
+                      } else {
+                          None
+                      }
+                  })
+                  .collect()
+    }
+}
+
+/// Rows visited by a sweep, skipping the first row of the direction since it has nothing to align to.
+fn sweep_rows(row_count: usize, downwards: bool) -> Vec<usize> {
+    if downwards {
+        (1..row_count).collect()
+    } else {
+        (0..row_count.saturating_sub(1)).rev().collect()
+    }
+}
+
+/// Places boxes of the given `sizes`. Each edge `(from, to)` pulls `to` into a row above `from`,
+/// like a superclass above its subclasses. Boxes without edges are arranged in a grid below.
+/// Rows are at least `min_gap` apart.
+pub fn layout(sizes: &[(u32, u32)], edges: &[(usize, usize)], min_gap: u32) -> Layout {
+    let mut edges = edges.iter().cloned().filter(|(from, to)| from != to).collect::<Vec<_>>();
+    edges.sort();
+    edges.dedup();
+
+    let (connected, isolated): (Vec<usize>, Vec<usize>) =
+        (0..sizes.len()).partition(|i| edges.iter().any(|(from, to)| from == i || to == i));
+
+    let mut graph = Graph {
+        nodes: Vec::new(),
+        order: Vec::new(),
+        links: Vec::new(),
+    };
+
+    if !edges.is_empty() {
+        add_layers(&mut graph, sizes, &connected, &remove_cycles(sizes.len(), &edges));
+        minimize_crossings(&mut graph);
+    }
+
+    let mut xs = assign_x(&graph);
+
+    let columns = (isolated.len() as f64).sqrt().ceil() as usize;
+    for chunk in isolated.chunks(cmp::max(columns, 1)) {
+        let row = graph.order.len();
+        let mut x = 0;
+
+        for &i in chunk {
+            graph.add_node(sizes[i].0, row, Some(i));
+            xs.push(x);
+            x += sizes[i].0 + NODE_SPACING;
+        }
+    }
+
+    let mut layout = Layout {
+        positions: vec![(0, 0); sizes.len()],
+        rows: vec![0; sizes.len()],
+        width: 0,
+        height: 0,
+    };
+
+    for (node, x) in graph.nodes.iter().zip(&xs) {
+        if let Some(i) = node.box_index {
+            layout.positions[i].0 = *x;
+            layout.rows[i] = node.row;
+            layout.width = cmp::max(layout.width, x + node.width);
+        }
+    }
+
+    // Every gap is high enough for one lane per edge running through it
+    let mut lane_counts = vec![0; graph.order.len()];
+    for (from, to) in &edges {
+        lane_counts[layout.edge_gap(*from, *to)] += 1;
+    }
+
+    let mut y = 0;
+    for (row, nodes) in graph.order.iter().enumerate() {
+        let boxes = nodes.iter().filter_map(|n| graph.nodes[*n].box_index).collect::<Vec<_>>();
+        let row_height = boxes.iter().map(|i| sizes[*i].1).max().unwrap_or(0);
+
+        for i in boxes {
+            layout.positions[i].1 = y;
+        }
+
+        let gap = cmp::max(min_gap, (lane_counts[row] + 1) * LANE_SPACING);
+        y += row_height + gap;
+    }
+
+    layout.height = y;
+    layout
+}
+
+/// Reverses the edges that close a cycle, so every edge can point upwards.
+fn remove_cycles(node_count: usize, edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // 0: unvisited, 1: on the current path, 2: done
+    fn visit(node: usize, edges: &[(usize, usize)], state: &mut Vec<u8>, result: &mut Vec<(usize, usize)>) {
+        state[node] = 1;
+
+        for &(from, to) in edges.iter().filter(|(from, _)| *from == node) {
+            match state[to] {
+                0 => {
+                    result.push((from, to));
+                    visit(to, edges, state, result);
+                },
+                1 => result.push((to, from)),
+                _ => result.push((from, to)),
+            }
+        }
+
+        state[node] = 2;
+    }
+
+    let mut state = vec![0; node_count];
+    let mut result = Vec::new();
+
+    for node in 0..node_count {
+        if state[node] == 0 {
+            visit(node, edges, &mut state, &mut result);
+        }
+    }
+
+    result
+}
+
+/// Puts every connected box one row below its lowest supertype and adds placeholders
+/// for edges spanning several rows.
+fn add_layers(graph: &mut Graph, sizes: &[(u32, u32)], connected: &[usize], edges: &[(usize, usize)]) {
+    fn row_of(node: usize, edges: &[(usize, usize)], rows: &mut Vec<Option<usize>>) -> usize {
+        if let Some(row) = rows[node] {
+            return row;
+        }
+
+        let row = edges.iter()
+                       .filter(|(from, _)| *from == node)
+                       .map(|&(_, to)| row_of(to, edges, rows) + 1)
+                       .max().unwrap_or(0);
+
+        rows[node] = Some(row);
+        row
+    }
+
+    let mut rows = vec![None; sizes.len()];
+    let mut node_of = vec![0; sizes.len()];
+
+    for &i in connected {
+        let row = row_of(i, edges, &mut rows);
+        node_of[i] = graph.add_node(sizes[i].0, row, Some(i));
+    }
+
+    for &(from, to) in edges {
+        let mut upper = node_of[to];
+
+        for row in rows[to].unwrap() + 1..rows[from].unwrap() {
+            let placeholder = graph.add_node(0, row, None);
+            graph.links.push((upper, placeholder));
+            upper = placeholder;
+        }
+
+        graph.links.push((upper, node_of[from]));
+    }
+}
+
+/// Barycenter heuristic: sweeps down and up, sorting every row by the average position of
+/// its neighbours in the row before, and keeps the order with the fewest crossings.
+fn minimize_crossings(graph: &mut Graph) {
+    let mut best_order = graph.order.clone();
+    let mut best_crossings = graph.count_crossings();
+
+    for sweep in 0..ORDERING_SWEEPS {
+        let downwards = sweep % 2 == 0;
+
+        for row in sweep_rows(graph.order.len(), downwards) {
+            let positions = graph.positions();
+
+            let mut keyed = graph.order[row].iter().map(|&node| {
+                let neighbours = graph.neighbours(node, downwards);
+
+                let barycenter = if neighbours.is_empty() {
+                    positions[node] as f64
+                } else {
+                    neighbours.iter().map(|n| positions[*n] as f64).sum::<f64>() / neighbours.len() as f64
+                };
+
+                (barycenter, node)
+            }).collect::<Vec<_>>();
+
+            keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            graph.order[row] = keyed.into_iter().map(|(_, node)| node).collect();
+        }
+
+        let crossings = graph.count_crossings();
+        if crossings < best_crossings {
+            best_crossings = crossings;
+            best_order = graph.order.clone();
+        }
+    }
+
+    graph.order = best_order;
+}
+
+/// Packs every row and then moves each node towards the average center of its neighbours,
+/// keeping the order of the row and the spacing between the nodes.
+fn assign_x(graph: &Graph) -> Vec<u32> {
+    let mut xs = vec![0; graph.nodes.len()];
+
+    for row in &graph.order {
+        let mut x = 0;
+
+        for node in row {
+            xs[*node] = x;
+            x += graph.nodes[*node].width + NODE_SPACING;
+        }
+    }
+
+    for sweep in 0..POSITIONING_SWEEPS {
+        let downwards = sweep % 2 == 0;
+
+        for row in sweep_rows(graph.order.len(), downwards) {
+            let mut min_x = 0;
+            let mut overshoot = 0;
+
+            for &node in &graph.order[row] {
+                let width = graph.nodes[node].width;
+                let neighbours = graph.neighbours(node, downwards);
+
+                let desired_x = if neighbours.is_empty() {
+                    xs[node]
+                } else {
+                    let center = neighbours.iter().map(|n| xs[*n] + graph.nodes[*n].width / 2).sum::<u32>()
+                                 / neighbours.len() as u32;
+                    center.saturating_sub(width / 2)
+                };
+
+                xs[node] = cmp::max(desired_x, min_x);
+                overshoot += xs[node] - desired_x;
+                min_x = xs[node] + width + NODE_SPACING;
+            }
+
+            // Nodes are only ever pushed to the right, so center the row on the desired positions again
+            let row_nodes = &graph.order[row];
+            let row_start = row_nodes.iter().map(|n| xs[*n]).min().unwrap_or(0);
+            let shift = cmp::min(overshoot / cmp::max(row_nodes.len() as u32, 1), row_start);
+
+            for node in row_nodes {
+                xs[*node] -= shift;
+            }
+        }
+    }
+
+    let min_x = xs.iter().cloned().min().unwrap_or(0);
+    xs.iter().map(|x| x - min_x).collect()
+}
@@ -0,0 +1,364 @@
+use crate::diagnostic::{Diagnostic, Severity};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Identifier,
+
+    ClassKeyword,
+    InterfaceKeyword,
+    EnumKeyword,
+    ExtendsKeyword,
+    ImplementsKeyword,
+    SuperKeyword,
+    PublicKeyword,
+    PrivateKeyword,
+    FinalKeyword,
+    AbstractKeyword,
+    ProtectedKeyword,
+    StaticKeyword,
+    DefaultKeyword,
+    SynchronizedKeyword,
+    TransientKeyword,
+    VolatileKeyword,
+    NativeKeyword,
+    StrictfpKeyword,
+    PackageKeyword,
+    ImportKeyword,
+
+    OpenCurly,
+    CloseCurly,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+    Less,
+    Greater,
+
+    SemiColon,
+    Comma,
+    Equal,
+    Dot,
+    Question,
+    Ampersand,
+    At,
+    /// Any other operator character, only relevant when skipping code
+    Operator,
+
+    StringLiteral,
+    TextBlock,
+    CharLiteral,
+    NumberLiteral,
+
+    Eof,
+}
+
+impl TokenKind {
+    fn keyword_from_str(ident: &str) -> TokenKind {
+        match ident {
+            "class" => TokenKind::ClassKeyword,
+            "interface" => TokenKind::InterfaceKeyword,
+            "enum" => TokenKind::EnumKeyword,
+            "extends" => TokenKind::ExtendsKeyword,
+            "implements" => TokenKind::ImplementsKeyword,
+            "super" => TokenKind::SuperKeyword,
+            "private" => TokenKind::PrivateKeyword,
+            "public" => TokenKind::PublicKeyword,
+            "static" => TokenKind::StaticKeyword,
+            "protected" => TokenKind::ProtectedKeyword,
+            "abstract" => TokenKind::AbstractKeyword,
+            "final" => TokenKind::FinalKeyword,
+            "default" => TokenKind::DefaultKeyword,
+            "synchronized" => TokenKind::SynchronizedKeyword,
+            "transient" => TokenKind::TransientKeyword,
+            "volatile" => TokenKind::VolatileKeyword,
+            "native" => TokenKind::NativeKeyword,
+            "strictfp" => TokenKind::StrictfpKeyword,
+            "package" => TokenKind::PackageKeyword,
+            "import" => TokenKind::ImportKeyword,
+
+            _ => TokenKind::Identifier,
+        }
+    }
+
+    /// Whether this is one of the modifier keywords in front of a declaration.
+    pub fn is_keyword(&self) -> bool {
+        matches!(self,
+            TokenKind::PrivateKeyword |
+            TokenKind::PublicKeyword |
+            TokenKind::StaticKeyword |
+            TokenKind::ProtectedKeyword |
+            TokenKind::AbstractKeyword |
+            TokenKind::FinalKeyword |
+            TokenKind::DefaultKeyword |
+            TokenKind::SynchronizedKeyword |
+            TokenKind::TransientKeyword |
+            TokenKind::VolatileKeyword |
+            TokenKind::NativeKeyword |
+            TokenKind::StrictfpKeyword)
+    }
+}
+
+/// A token borrowing its text from the source. `start` and `end` are byte offsets.
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'s> {
+    pub kind: TokenKind,
+    pub text: &'s str,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl<'s> Token<'s> {
+    pub fn new(kind: TokenKind, text: &'s str, start: usize, end: usize) -> Self {
+        Self {
+            kind, text, start, end
+        }
+    }
+}
+
+/// Splits java source into tokens in a single pass. Comments and whitespace are
+/// dropped, problems end up in `diagnostics`.
+pub struct Lexer<'s> {
+    source: &'s str,
+    pos: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'s> Lexer<'s> {
+    pub fn new(source: &'s str) -> Self {
+        Self {
+            source,
+            pos: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Lexes the whole source. The last token is always an `Eof` token.
+    pub fn tokenize(&mut self) -> Vec<Token<'s>> {
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = self.next_token();
+            tokens.push(token);
+
+            if token.kind == TokenKind::Eof {
+                break;
+            }
+        }
+
+        tokens
+    }
+
+    fn nth_chr(&self, offset: usize) -> char {
+        self.source[self.pos..].chars().nth(offset).unwrap_or('\0')
+    }
+
+    fn curr_chr(&self) -> char {
+        self.source[self.pos..].chars().next().unwrap_or('\0')
+    }
+
+    fn bump(&mut self) {
+        if self.pos < self.source.len() {
+            self.pos += self.curr_chr().len_utf8();
+        }
+    }
+
+    fn bump_while(&mut self, pred: fn (char) -> bool) {
+        while self.pos < self.source.len() && (pred)(self.curr_chr()) {
+            self.bump();
+        }
+    }
+
+    fn report(&mut self, severity: Severity, message: String, start: usize) {
+        self.diagnostics.push(Diagnostic::new(severity, message, self.source, start, self.pos));
+    }
+
+    fn token(&self, kind: TokenKind, start: usize) -> Token<'s> {
+        Token::new(kind, &self.source[start..self.pos], start, self.pos)
+    }
+
+    fn single(&mut self, kind: TokenKind) -> Token<'s> {
+        let start = self.pos;
+        self.bump();
+        self.token(kind, start)
+    }
+
+    /// Skips whitespace and comments in front of the next token.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.bump_while(|c| c.is_whitespace());
+
+            if self.curr_chr() == '/' && self.nth_chr(1) == '/' {
+                self.bump_while(|c| c != '\n');
+            } else if self.curr_chr() == '/' && self.nth_chr(1) == '*' {
+                let start = self.pos;
+                self.pos += 2;
+
+                while self.pos < self.source.len() && !self.source[self.pos..].starts_with("*/") {
+                    self.bump();
+                }
+
+                if self.pos >= self.source.len() {
+                    self.report(Severity::Error, "Unterminated comment".to_string(), start);
+                } else {
+                    self.pos += 2;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Token<'s> {
+        loop {
+            self.skip_trivia();
+
+            if self.pos >= self.source.len() {
+                return self.token(TokenKind::Eof, self.pos);
+            }
+
+            let token = match self.curr_chr() {
+                '{' => self.single(TokenKind::OpenCurly),
+                '}' => self.single(TokenKind::CloseCurly),
+                '(' => self.single(TokenKind::OpenParen),
+                ')' => self.single(TokenKind::CloseParen),
+                '[' => self.single(TokenKind::OpenBracket),
+                ']' => self.single(TokenKind::CloseBracket),
+                '<' => self.single(TokenKind::Less),
+                // Always a single token, so `>>` closes two type argument lists
+                '>' => self.single(TokenKind::Greater),
+                ';' => self.single(TokenKind::SemiColon),
+                ',' => self.single(TokenKind::Comma),
+                '=' => self.single(TokenKind::Equal),
+                '.' if self.nth_chr(1).is_ascii_digit() => self.consume_number(),
+                '.' => self.single(TokenKind::Dot),
+                '?' => self.single(TokenKind::Question),
+                '&' => self.single(TokenKind::Ampersand),
+                '@' => self.single(TokenKind::At),
+
+                c if c.is_alphabetic() || c == '_' || c == '$' => {
+                    let start = self.pos;
+                    self.bump_while(|c| c.is_alphabetic() || c == '_' || c == '$' || c.is_ascii_digit());
+                    self.token(TokenKind::keyword_from_str(&self.source[start..self.pos]), start)
+                },
+
+                c if c.is_ascii_digit() => self.consume_number(),
+
+                '"' if self.source[self.pos..].starts_with("\"\"\"") => self.consume_text_block(),
+                '"' => self.consume_quoted('"', TokenKind::StringLiteral),
+                '\'' => self.consume_quoted('\'', TokenKind::CharLiteral),
+
+                '+' | '-' | '*' | '/' | '%' | '!' | '~' | '^' | '|' | ':' => self.single(TokenKind::Operator),
+
+                c => {
+                    let start = self.pos;
+                    self.bump();
+                    self.report(Severity::Warning, format!("Ignoring bad character input '{}'", c), start);
+                    continue;
+                }
+            };
+
+            return token;
+        }
+    }
+
+    fn consume_text_block(&mut self) -> Token<'s> {
+        let start = self.pos;
+        self.pos += 3;
+
+        while self.pos < self.source.len() && !self.source[self.pos..].starts_with("\"\"\"") {
+            if self.curr_chr() == '\\' {
+                self.bump();
+            }
+            self.bump();
+        }
+
+        if self.pos >= self.source.len() {
+            self.report(Severity::Error, "Unterminated text block".to_string(), start);
+        } else {
+            self.pos += 3;
+        }
+
+        self.token(TokenKind::TextBlock, start)
+    }
+
+    /// String and char literals, which may not span multiple lines.
+    fn consume_quoted(&mut self, quote: char, kind: TokenKind) -> Token<'s> {
+        let start = self.pos;
+        self.bump();
+
+        while self.pos < self.source.len() && self.curr_chr() != quote && self.curr_chr() != '\n' {
+            if self.curr_chr() == '\\' {
+                self.bump();
+            }
+            self.bump();
+        }
+
+        if self.curr_chr() == quote && self.pos < self.source.len() {
+            self.bump();
+        } else {
+            self.report(Severity::Error, format!("Unterminated literal starting with {}", quote), start);
+        }
+
+        self.token(kind, start)
+    }
+
+    /// Integer and floating point literals in all bases, including suffixes,
+    /// underscores and signed exponents like `1.5e-3`.
+    fn consume_number(&mut self) -> Token<'s> {
+        let start = self.pos;
+        let is_hex = self.curr_chr() == '0' && (self.nth_chr(1) == 'x' || self.nth_chr(1) == 'X');
+
+        loop {
+            let curr = self.curr_chr();
+
+            if curr.is_alphanumeric() || curr == '_' || curr == '.' {
+                self.bump();
+
+                let is_exponent = if is_hex { curr == 'p' || curr == 'P' } else { curr == 'e' || curr == 'E' };
+                if is_exponent && (self.curr_chr() == '+' || self.curr_chr() == '-') {
+                    self.bump();
+                }
+            } else {
+                break;
+            }
+        }
+
+        self.token(TokenKind::NumberLiteral, start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<(TokenKind, &str)> {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+        assert!(lexer.diagnostics.is_empty(), "unexpected diagnostics for {:?}", source);
+
+        tokens.iter().map(|t| (t.kind, t.text)).collect()
+    }
+
+    #[test]
+    fn braces_in_literals() {
+        assert_eq!(tokens(r#""}""#), [(TokenKind::StringLiteral, r#""}""#), (TokenKind::Eof, "")]);
+        assert_eq!(tokens("'}'"), [(TokenKind::CharLiteral, "'}'"), (TokenKind::Eof, "")]);
+        assert_eq!(tokens(r"'\''"), [(TokenKind::CharLiteral, r"'\''"), (TokenKind::Eof, "")]);
+    }
+
+    #[test]
+    fn escaped_quotes_in_text_block() {
+        let source = "\"\"\"\n    a \\\"\"\" } b\n    \"\"\";";
+
+        assert_eq!(tokens(source), [(TokenKind::TextBlock, &source[..source.len() - 1]),
+                                    (TokenKind::SemiColon, ";"), (TokenKind::Eof, "")]);
+    }
+
+    #[test]
+    fn braces_in_comments() {
+        let source = "/* } { */ a // }\n b";
+
+        assert_eq!(tokens(source), [(TokenKind::Identifier, "a"), (TokenKind::Identifier, "b"),
+                                    (TokenKind::Eof, "")]);
+    }
+}
@@ -1,138 +1,207 @@
-use std::io;
-use std::fs;
-use std::cmp;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+mod cli;
+use cli::{Cli, Command, Format};
 
 mod java;
-use java::*;
 
+mod lexer;
 mod parser;
-use parser::Parser;
 
+mod diagnostic;
+
+mod project;
+use project::Project;
+
+mod resolve;
+mod relation;
+
+mod canvas;
+mod font;
+use font::{BuiltinFont, Fonts};
 mod image;
 use image::Image;
+mod svg;
+use svg::Svg;
+
+mod layout;
+mod route;
+mod diagram;
+use diagram::DiagramOptions;
+mod plantuml;
 
 use raylib::prelude::*;
-use freetype::face;
-use freetype::Library;
+use freetype::{face, Library};
 
 const WINDOW_WIDTH: i32 = 720;
 const WINDOW_HEIGHT: i32 = 1280;
-const FONT_SIZE: u32 = 40;
-const UML_PADDING: u32 = 20;
-const UML_ITEM_HEIGHT: u32 = UML_PADDING * 2 + FONT_SIZE;
 
-fn measure_class(class: &Class, font: &face::Face) -> (u32, u32) {
-    let field_strs = class.fields.iter().map(|f| f.to_string()).collect::<Vec<_>>();
-    let method_strs = class.methods.iter().map(|f| f.to_string()).collect::<Vec<_>>();
-
-    let max_field_text_width = field_strs.iter()
-                                         .map(|f| Image::measure_text(f, FONT_SIZE as usize, font))
-                                         .max().unwrap_or(0);
-    
-    let max_method_text_width = method_strs.iter()
-                                           .map(|f| Image::measure_text(f, FONT_SIZE as usize, font))
-                                           .max().unwrap_or(0);
+fn main() {
+    std::process::exit(run());
+}
 
-    let class_name_width = Image::measure_text(&class.name, FONT_SIZE as usize, font);
+fn run() -> i32 {
+    let cli = match cli::parse_args(std::env::args().skip(1).collect()) {
+        Ok(cli) => cli,
+        Err(message) => {
+            eprintln!("error: {}\n\nRun with --help to see the usage.", message);
+            return cli::EXIT_USAGE;
+        },
+    };
 
-    let uml_width = cmp::max(class_name_width, cmp::max(max_field_text_width, max_method_text_width)) + UML_PADDING * 2;
-    let uml_height = (field_strs.len() + method_strs.len() + 1) as u32 * UML_ITEM_HEIGHT;
+    if cli.command == Command::Help {
+        print!("{}", cli::USAGE);
+        return cli::EXIT_SUCCESS;
+    }
 
-    (uml_width, uml_height)
-}
+    if let Some(missing) = cli.inputs.iter().find(|input| input.as_os_str() != "-" && !input.exists()) {
+        eprintln!("error: the input `{}` does not exist", missing.display());
+        return cli::EXIT_IO;
+    }
 
-fn render_class_new(img: &mut Image, class: &Class, start_x: u32, start_y: u32, font: &face::Face) {
-    let field_strs = class.fields.iter().map(|f| f.to_string()).collect::<Vec<_>>();
-    let method_strs = class.methods.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+    let project = match Project::load(&cli.inputs) {
+        Ok(project) => project,
+        Err(err) => {
+            eprintln!("error: could not read the input: {}", err);
+            return cli::EXIT_IO;
+        },
+    };
 
-    let max_field_text_width = field_strs.iter()
-                                         .map(|f| Image::measure_text(f, FONT_SIZE as usize, font))
-                                         .max().unwrap_or(0);
-    
-    let max_method_text_width = method_strs.iter()
-                                           .map(|f| Image::measure_text(f, FONT_SIZE as usize, font))
-                                           .max().unwrap_or(0);
+    for d in &project.diagnostics {
+        eprintln!("{}", d.render());
+    }
 
-    let class_name_width = Image::measure_text(&class.name, FONT_SIZE as usize, font);
+    // Broken members are left out of their classes, everything else can still be drawn
+    if project.has_errors() && (cli.command == Command::Check || cli.strict) {
+        return cli::EXIT_INVALID_INPUT;
+    }
+    if cli.command == Command::Check {
+        return cli::EXIT_SUCCESS;
+    }
 
-    let uml_width = cmp::max(class_name_width, cmp::max(max_field_text_width, max_method_text_width)) + UML_PADDING * 2;
-    let uml_height = (field_strs.len() + method_strs.len() + 1) as u32 * UML_ITEM_HEIGHT;
+    // Faces are freed together with their library, so it has to outlive them
+    let lib = match Library::init() {
+        Ok(lib) => lib,
+        Err(err) => {
+            eprintln!("error: could not initialize freetype: {:?}", err);
+            return cli::EXIT_IO;
+        },
+    };
 
-    img.render_rect(start_x, start_y, uml_width, uml_height, 0);
-    img.render_text(start_x + uml_width / 2 - class_name_width / 2, start_y + UML_PADDING,
-                    &class.name, FONT_SIZE as usize, font);
+    let fonts = match load_fonts(&lib, &cli) {
+        Ok(fonts) => fonts,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return cli::EXIT_IO;
+        },
+    };
 
-    img.render_horizontal_line(start_y + UML_ITEM_HEIGHT, start_x, start_x + uml_width, 0);
+    let classes = project.classes();
 
-    for (i, text) in field_strs.iter().enumerate() {
-        img.render_text(start_x + UML_PADDING, start_y + (i as u32 + 1) * UML_ITEM_HEIGHT + UML_PADDING, text,
-                        FONT_SIZE as usize, font);
+    if cli.command == Command::View {
+        let img: Image = diagram::render_diagram(&classes, &fonts, &cli.options);
+        view(&img);
+        return cli::EXIT_SUCCESS;
     }
-    
-    let method_start_y = start_y + UML_ITEM_HEIGHT * (field_strs.len() as u32 + 1);
-    img.render_horizontal_line(method_start_y, start_x, start_x + uml_width, 0);
 
-    for (i, text) in method_strs.iter().enumerate() {
-        img.render_text(start_x + UML_PADDING, method_start_y + (i as u32) * UML_ITEM_HEIGHT + UML_PADDING,
-                        text, FONT_SIZE as usize, font);
+    let output_path = cli.output_path();
+    match write_diagram(&classes, &fonts, &cli.options, cli.output_format(), output_path.as_deref()) {
+        Ok(()) => cli::EXIT_SUCCESS,
+        Err(err) => {
+            eprintln!("error: could not write {}: {}", output_path.as_deref().unwrap_or("to stdout"), err);
+            cli::EXIT_IO
+        },
     }
 }
 
-fn main() -> io::Result<()> {
-    let source_file = if let Some(name) = std::env::args().nth(1) {
-        name
-    } else {
-        "sample.java".to_string()
+/// The font of the command line with the faces given for its styles.
+fn load_fonts(lib: &Library, cli: &Cli) -> Result<Fonts, String> {
+    // Without a font, a `font.ttf` in the working directory is used before the built-in font
+    let font_name = match &cli.font {
+        Some(font) => font.clone(),
+        None if Path::new("font.ttf").is_file() => "font.ttf".to_string(),
+        None => "sans".to_string(),
     };
-    
-    let font_name = if let Some(name) = std::env::args().nth(2) {
-        name
-    } else {
-        "font.ttf".to_string()
+
+    let fonts = match BuiltinFont::from_name(&font_name) {
+        Some(builtin) => Fonts::builtin(lib, builtin),
+        None => Fonts::load(lib, &font_name),
     };
+    let mut fonts = fonts.map_err(|err| font_error(&font_name, err))?;
 
-    let source = fs::read_to_string(&source_file)?;
-    let mut parser = Parser::new(&source);
-    let class = parser.parse_class_def();
+    // Explicitly given faces win over the ones found next to the regular font
+    if let Some(path) = &cli.bold_font {
+        fonts.bold = Some(load_face(lib, path)?);
+    }
+    if let Some(path) = &cli.italic_font {
+        fonts.italic = Some(load_face(lib, path)?);
+    }
+    if let Some(path) = &cli.bold_italic_font {
+        fonts.bold_italic = Some(load_face(lib, path)?);
+    }
+    for (i, path) in cli.fallback_fonts.iter().enumerate() {
+        fonts.fallbacks.insert(i, load_face(lib, path)?);
+    }
 
-    if parser.diagnostics.len() > 0 {
-        for d in parser.diagnostics {
-            println!("(pos {}) Parsing Error: {}", d.pos, d.message);
-        }
+    Ok(fonts)
+}
+
+fn load_face(lib: &Library, path: &str) -> Result<face::Face, String> {
+    lib.new_face(path, 0).map_err(|err| font_error(path, err))
+}
 
-        std::process::exit(1);
+fn font_error(path: &str, err: freetype::Error) -> String {
+    if Path::new(path).is_file() {
+        format!("could not load the font `{}`: {:?}", path, err)
+    } else {
+        format!("the font `{}` does not exist", path)
     }
+}
 
-    let class = class.unwrap();
+/// Writes the diagram in `format` to the file at `path`, or to stdout.
+fn write_diagram(classes: &[&java::Class], fonts: &Fonts, options: &DiagramOptions, format: Format,
+                 path: Option<&str>) -> io::Result<()> {
+    let mut out: Box<dyn Write> = match path {
+        Some(path) => Box::new(io::BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+
+    match format {
+        Format::Svg => {
+            let svg: Svg = diagram::render_diagram(classes, fonts, options);
+            svg.write(&mut out)?;
+        },
+        Format::Png => {
+            let img: Image = diagram::render_diagram(classes, fonts, options);
+            img.write_png(&mut out)?;
+        },
+        Format::Ppm => {
+            let img: Image = diagram::render_diagram(classes, fonts, options);
+            img.write_ppm(&mut out)?;
+        },
+        Format::PlantUml => out.write_all(plantuml::export(classes, options).as_bytes())?,
+    }
+
+    out.flush()
+}
 
+fn view(img: &Image) {
     set_trace_log(TraceLogType::LOG_NONE);
     let (mut rl, thread) = raylib::init().size(WINDOW_WIDTH, WINDOW_HEIGHT).title("UML-san uwu").build();
     rl.set_target_fps(60);
     rl.set_window_state(rl.get_window_state().set_window_resizable(true));
 
-    let lib = Library::init().unwrap();
-    let face = lib.new_face(&font_name, 0).unwrap();
-
-    const CLASS_PADDING: u32 = 2;
-    const DOUBLE_PAD: u32 = CLASS_PADDING * 2 + 1;
-    
-    let (class_width, class_height) = measure_class(&class, &face);
-
-    let mut img = Image::new(class_width as usize + DOUBLE_PAD as usize, class_height as usize + DOUBLE_PAD as usize);
-    render_class_new(&mut img, &class, CLASS_PADDING, CLASS_PADDING, &face);
-    img.save_to_file_ppm("save.ppm")?;
-
     while !rl.window_should_close() {
         let mut d = rl.begin_drawing(&thread);
         d.clear_background(Color::RAYWHITE);
 
-        for x in 0..class_width + DOUBLE_PAD {
-            for y in 0..class_height + DOUBLE_PAD {
-                let grey_val = img.get(x as u32, y as u32);
-                d.draw_pixel(x as i32, y as i32, Color::new(grey_val, grey_val, grey_val, 255))
+        for x in 0..img.width() as u32 {
+            for y in 0..img.height() as u32 {
+                let pixel = img.get(x, y);
+                d.draw_pixel(x as i32, y as i32, Color::new(pixel.r, pixel.g, pixel.b, pixel.a))
             }
         }
     }
-
-    Ok(())
 }
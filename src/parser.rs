@@ -1,254 +1,423 @@
 use crate::java::*;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::lexer::{Lexer, Token, TokenKind};
 
 pub struct Parser<'s> {
     source: &'s str,
+    tokens: Vec<Token<'s>>,
+    /// Index of the next token in `tokens`.
     pos: usize,
+    anonymous_count: usize,
+    /// Set while looking ahead, when failing parses are expected and not worth reporting.
+    quiet: bool,
     pub diagnostics: Vec<Diagnostic>,
 }
 
 impl<'s> Parser<'s> {
     pub fn new(source: &'s str) -> Self {
+        let mut lexer = Lexer::new(source);
+        let tokens = lexer.tokenize();
+
         Self {
             source,
+            tokens,
             pos: 0,
-            diagnostics: Vec::new(),
+            anonymous_count: 0,
+            quiet: false,
+            diagnostics: lexer.diagnostics,
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-enum TokenKind {
-    Identifier,
-
-    ClassKeyword,
-    PublicKeyword,
-    PrivateKeyword,
-    FinalKeyword,
-    AbstractKeyword,
-    ProtectedKeyword,
-    StaticKeyword,
-    
-    OpenCurly,
-    CloseCurly,
-    OpenParen,
-    CloseParen,
-    
-    SemiColon,
-    Comma,
-    Equal,
-    
-    Eof,
+fn modifiers_from_keywords(keywords: &[Token]) -> Modifiers {
+    let mut modifiers = Modifiers::new();
+
+    for keyword in keywords {
+        match keyword.kind {
+            TokenKind::StaticKeyword => modifiers.is_static = true,
+            TokenKind::AbstractKeyword => modifiers.is_abstract = true,
+            TokenKind::FinalKeyword => modifiers.is_final = true,
+            TokenKind::DefaultKeyword => modifiers.is_default = true,
+            TokenKind::SynchronizedKeyword => modifiers.is_synchronized = true,
+            _ => ()
+        }
+    }
+
+    modifiers
 }
 
-impl TokenKind {
-    fn keyword_from_str(ident: &str) -> TokenKind {
-        match ident {
-            "class" => TokenKind::ClassKeyword,
-            "private" => TokenKind::PrivateKeyword,
-            "public" => TokenKind::PublicKeyword,
-            "static" => TokenKind::StaticKeyword,
-            "protected" => TokenKind::ProtectedKeyword,
-            "abstract" => TokenKind::AbstractKeyword,
-            "final" => TokenKind::FinalKeyword,
+impl<'s> Parser<'s> {
+    fn report(&mut self, severity: Severity, message: String, start: usize, end: usize) {
+        if self.quiet {
+            return;
+        }
 
-            _ => TokenKind::Identifier,
+        // Speculative parses can run into the same problem twice, but each is only reported once
+        if !self.diagnostics.iter().any(|d| d.start == start && d.message == message) {
+            self.diagnostics.push(Diagnostic::new(severity, message, self.source, start, end));
         }
     }
 
-    fn is_keyword(&self) -> bool {
-        match self {
-            TokenKind::PrivateKeyword |
-            TokenKind::PublicKeyword |
-            TokenKind::StaticKeyword |
-            TokenKind::ProtectedKeyword |
-            TokenKind::AbstractKeyword |
-            TokenKind::FinalKeyword => true,
-            _ => false
-        }
+    /// Runs `parse` without reporting anything and moves back to the current token afterwards.
+    fn look_ahead<T>(&mut self, parse: impl FnOnce(&mut Self) -> T) -> T {
+        let (pos, quiet) = (self.pos, self.quiet);
+        self.quiet = true;
+
+        let result = parse(self);
+
+        self.pos = pos;
+        self.quiet = quiet;
+        result
     }
-}
 
-#[derive(Clone)]
-struct Token {
-    kind: TokenKind,
-    text: String,
-    pos: usize,
-}
+    fn report_diagnostic(&mut self, message: String, start: usize, end: usize) {
+        self.report(Severity::Error, message, start, end);
+    }
 
-impl Token {
-    fn new(kind: TokenKind, text: String, pos: usize) -> Self {
-        Self {
-            kind, text, pos
+    /// The token `offset` tokens ahead, or the final `Eof` token.
+    fn nth_token(&self, offset: usize) -> Token<'s> {
+        let index = (self.pos + offset).min(self.tokens.len() - 1);
+        self.tokens[index]
+    }
+
+    fn curr_token(&self) -> Token<'s> {
+        self.nth_token(0)
+    }
+
+    fn consume_token(&mut self) -> Token<'s> {
+        let token = self.curr_token();
+
+        if token.kind != TokenKind::Eof {
+            self.pos += 1;
         }
+
+        token
     }
-}
 
-pub struct Diagnostic {
-    pub message: String,
-    pub pos: usize,
-}
+    /// Consumes the next token if it is of the given kind, otherwise reports it
+    /// and leaves it in place for error recovery.
+    fn consume_expected(&mut self, kind: TokenKind) -> Option<Token<'s>> {
+        let token = self.curr_token();
 
+        if token.kind != kind {
+            self.report_diagnostic(
+                format!("Unexpected Token of kind {:?}, expected {:?}", 
+                        token.kind, kind),
+                token.start, token.end);
+            None
+        } else {
+            Some(self.consume_token())
+        }
 
-impl Parser<'_> {
-    fn nth_chr(&self, offset: usize) -> char {
-        match self.source.chars().nth(self.pos + offset) {
-            Some(c) => c,
-            None => '\0'
+    }
+
+    fn consume_optional(&mut self, kind: TokenKind) -> bool {
+        if self.curr_token().kind == kind {
+            self.consume_token();
+            true
+        } else {
+            false
         }
     }
 
-    fn curr_chr(&self) -> char {
-        match self.source.chars().nth(self.pos) {
-            Some(c) => c,
-            None => '\0',
+    /// Skips tokens up to and including the `close` that matches an already consumed
+    /// `open` and returns the source text in between.
+    fn skip_balanced(&mut self, open: TokenKind, close: TokenKind) -> String {
+        let start = self.tokens[self.pos - 1].end;
+        let mut end = start;
+        let mut depth = 1;
+
+        loop {
+            let token = self.consume_token();
+
+            if token.kind == TokenKind::Eof {
+                break;
+            } else if token.kind == open {
+                depth += 1;
+            } else if token.kind == close {
+                depth -= 1;
+                if depth == 0 {
+                    end = token.start;
+                    break;
+                }
+            }
+
+            end = token.end;
         }
+
+        self.source[start..end].to_string()
     }
 
-    fn chr_drop_while(&mut self, pred: fn (char) -> bool) {
-        while (pred)(self.curr_chr()) {
-            self.pos += 1;
+    /// Parses modifier keywords together with the annotations mixed in between them.
+    fn parse_keywords(&mut self) -> (Vec<Token<'s>>, Vec<Annotation>) {
+        let mut keywords = Vec::new();
+        let mut annotations = Vec::new();
+        
+        loop {
+            if self.curr_token().kind.is_keyword() {
+                keywords.push(self.consume_token());
+            } else if self.sealed_modifier_len() > 0 {
+                // Which classes may extend a type is not part of the diagram
+                self.pos += self.sealed_modifier_len();
+            } else if self.at_annotation() {
+                match self.parse_annotation() {
+                    Some(annotation) => annotations.push(annotation),
+                    None => break,
+                }
+            } else {
+                break;
+            }
         }
+
+        (keywords, annotations)
     }
-    
-    fn chr_take_while(&mut self, pred: fn (char) -> bool) -> String {
-        let mut out = String::new();
-        let mut curr: char;
 
-        while {
-            curr = self.curr_chr();
-            (pred)(curr)
-        } {
-            self.pos += 1;
-            out.push(curr);
+    /// Number of tokens of a `sealed` or `non-sealed` modifier at the current position, 0 if there is none.
+    /// Both are plain identifiers unless other modifiers or a type declaration follow.
+    fn sealed_modifier_len(&self) -> usize {
+        let token = self.curr_token();
+
+        let len = if token.kind == TokenKind::Identifier && token.text == "sealed" {
+            1
+        } else if token.kind == TokenKind::Identifier && token.text == "non"
+                  && self.nth_token(1).text == "-" && self.nth_token(1).start == token.end
+                  && self.nth_token(2).text == "sealed" && self.nth_token(2).start == self.nth_token(1).end {
+            3
+        } else {
+            return 0;
+        };
+
+        let next = self.nth_token(len);
+        match next.kind {
+            TokenKind::ClassKeyword | TokenKind::InterfaceKeyword | TokenKind::At => len,
+            kind if kind.is_keyword() => len,
+            _ => 0,
         }
+    }
 
-        out
+    /// Whether the upcoming tokens are an annotation, but not an `@interface` declaration.
+    fn at_annotation(&self) -> bool {
+        self.curr_token().kind == TokenKind::At && self.nth_token(1).kind != TokenKind::InterfaceKeyword
     }
 
-    fn report_diagnostic(&mut self, message: String, pos: usize) {
-        self.diagnostics.push(Diagnostic { message, pos })
+    fn parse_annotation(&mut self) -> Option<Annotation> {
+        self.consume_expected(TokenKind::At)?;
+        let name = self.parse_qualified_name()?;
+
+        let arguments = if self.consume_optional(TokenKind::OpenParen) {
+            Some(self.skip_balanced(TokenKind::OpenParen, TokenKind::CloseParen).trim().to_string())
+        } else {
+            None
+        };
+
+        Some(Annotation::new(name, arguments))
     }
 
-    fn consume_token(&mut self) -> Token {
-        self.chr_drop_while(|c| c.is_whitespace());
-        match self.curr_chr() {
-            '\0' => {
-                Token::new(TokenKind::Eof, "\0".to_string(), self.pos)
-            },
-            
-            '{' => {
-                self.pos += 1;
-                Token::new(TokenKind::OpenCurly, "{".to_string(), self.pos - 1)
-            },
-            
-            '}' => {
-                self.pos += 1;
-                Token::new(TokenKind::CloseCurly, "}".to_string(), self.pos - 1)
-            },
+    fn parse_qualified_name(&mut self) -> Option<String> {
+        let mut name = self.consume_expected(TokenKind::Identifier)?.text.to_string();
 
-            '(' => {
-                self.pos += 1;
-                Token::new(TokenKind::OpenParen, "(".to_string(), self.pos - 1)
-            },
+        while self.curr_token().kind == TokenKind::Dot {
+            let pos = self.pos;
+            self.consume_token();
 
-            ')' => {
-                self.pos += 1;
-                Token::new(TokenKind::CloseParen, ")".to_string(), self.pos - 1)
-            },
-            
-            ';' => {
-                self.pos += 1;
-                Token::new(TokenKind::SemiColon, ";".to_string(), self.pos - 1)
-            },
+            if self.curr_token().kind != TokenKind::Identifier {
+                self.pos = pos;
+                break;
+            }
 
-            ',' => {
-                self.pos += 1;
-                Token::new(TokenKind::Comma, ",".to_string(), self.pos - 1)
-            },
-            
-            '=' => {
-                self.pos += 1;
-                Token::new(TokenKind::Equal, "=".to_string(), self.pos - 1)
-            },
+            name.push('.');
+            name.push_str(self.consume_token().text);
+        }
+
+        Some(name)
+    }
+
+    fn parse_type(&mut self) -> Option<Type> {
+        // Type annotations like `List<@NonNull String>` are not part of the model
+        while self.at_annotation() {
+            self.parse_annotation()?;
+        }
+
+        let name = self.parse_qualified_name()?;
+
+        let mut arguments = Vec::new();
+        if self.consume_optional(TokenKind::Less) {
+            while self.curr_token().kind != TokenKind::Greater {
+                arguments.push(self.parse_type_argument()?);
 
-            c if c.is_alphabetic() => {
-                let start = self.pos;
-                let ident = self.chr_take_while(|c| c.is_alphabetic() || c == '_' || c.is_digit(10) || c == '[' || c == ']');
-                Token::new(TokenKind::keyword_from_str(&ident), ident, start)
+                if !self.consume_optional(TokenKind::Comma) {
+                    break;
+                }
             }
 
-            '/' => {
-                self.pos += 1;
-                if self.curr_chr() == '/' {
-                    while self.curr_chr() != '\n' && self.curr_chr() != '\0' {
-                        self.pos += 1;
-                    }
-                } else if self.curr_chr() == '*' {
-                    self.pos += 1;
-                    while self.curr_chr() != '*' || self.nth_chr(1) != '/' {
-                        self.pos += 1;
+            self.consume_expected(TokenKind::Greater)?;
+        }
+
+        let mut array_dimensions = 0;
+        while self.consume_optional(TokenKind::OpenBracket) {
+            self.consume_expected(TokenKind::CloseBracket)?;
+            array_dimensions += 1;
+        }
+
+        // Varargs (`String... args`) are arrays as far as the diagram is concerned
+        if self.consume_optional(TokenKind::Dot) {
+            self.consume_expected(TokenKind::Dot)?;
+            self.consume_expected(TokenKind::Dot)?;
+            array_dimensions += 1;
+        }
+
+        Some(Type::new(name, arguments, array_dimensions))
+    }
+
+    fn parse_type_argument(&mut self) -> Option<TypeArgument> {
+        if !self.consume_optional(TokenKind::Question) {
+            return Some(TypeArgument::Type(self.parse_type()?));
+        }
+
+        if self.consume_optional(TokenKind::ExtendsKeyword) {
+            Some(TypeArgument::Wildcard(Some(WildcardBound::Extends(self.parse_type()?))))
+        } else if self.consume_optional(TokenKind::SuperKeyword) {
+            Some(TypeArgument::Wildcard(Some(WildcardBound::Super(self.parse_type()?))))
+        } else {
+            Some(TypeArgument::Wildcard(None))
+        }
+    }
+
+    fn parse_type_parameters(&mut self) -> Option<Vec<TypeParameter>> {
+        let mut type_parameters = Vec::new();
+
+        if !self.consume_optional(TokenKind::Less) {
+            return Some(type_parameters);
+        }
+
+        while self.curr_token().kind != TokenKind::Greater {
+            let name = self.consume_expected(TokenKind::Identifier)?;
+            let mut bounds = Vec::new();
+
+            if self.consume_optional(TokenKind::ExtendsKeyword) {
+                loop {
+                    bounds.push(self.parse_type()?);
+
+                    if !self.consume_optional(TokenKind::Ampersand) {
+                        break;
                     }
-                    self.pos += 2;
                 }
-                
-                self.consume_token()
             }
 
-            c @ _ => {
-                self.report_diagnostic(format!("Bad character input '{}'", c), self.pos);
-                self.pos += 1;
-                self.consume_token()
+            type_parameters.push(TypeParameter::new(name.text.to_string(), bounds));
+
+            if !self.consume_optional(TokenKind::Comma) {
+                break;
             }
         }
+
+        self.consume_expected(TokenKind::Greater)?;
+        Some(type_parameters)
     }
 
-    fn curr_token(&mut self) -> Token {
-        let pos = self.pos;
-        let tok = self.consume_token();
-        self.pos = pos;
-        tok
+    fn parse_parameters(&mut self) -> Option<Vec<Parameter>> {
+        self.consume_expected(TokenKind::OpenParen)?;
+        let mut start_pos = self.pos;
+        let mut params = Vec::new();
+
+        while self.curr_token().kind != TokenKind::CloseParen {
+            let (_, annotations) = self.parse_keywords();
+            let param_type = self.parse_type()?;
+            let name = self.consume_expected(TokenKind::Identifier)?;
+            let mut param = Parameter::new(param_type, name.text.to_string());
+            param.annotations = annotations;
+
+            params.push(param);
+
+            if self.pos == start_pos {
+                break;
+            }
+
+            start_pos = self.pos;
+
+            if self.curr_token().kind != TokenKind::Comma {
+                break;
+            }
+
+            self.consume_expected(TokenKind::Comma)?;
+        }
+
+        self.consume_expected(TokenKind::CloseParen)?;
+        Some(params)
     }
 
-    fn consume_expected(&mut self, kind: TokenKind) -> Option<Token> {
-        let token = self.consume_token();
+    fn parse_enum_constants(&mut self) -> Option<Vec<EnumConstant>> {
+        let mut constants = Vec::new();
 
-        if token.kind != kind {
-            self.report_diagnostic(
-                format!("Unexpected Token of kind {:?}, expected {:?}", 
-                        token.kind, kind),
-                token.pos);
-            None
-        } else {
-            Some(token)
+        loop {
+            let (_, annotations) = self.parse_keywords();
+            if self.curr_token().kind != TokenKind::Identifier {
+                break;
+            }
+
+            let name = self.consume_token();
+
+            let arguments = if self.consume_optional(TokenKind::OpenParen) {
+                Some(self.skip_balanced(TokenKind::OpenParen, TokenKind::CloseParen).trim().to_string())
+            } else {
+                None
+            };
+
+            // Constant specific class bodies are not part of the diagram
+            if self.consume_optional(TokenKind::OpenCurly) {
+                self.skip_balanced(TokenKind::OpenCurly, TokenKind::CloseCurly);
+            }
+
+            let mut constant = EnumConstant::new(name.text.to_string(), arguments);
+            constant.annotations = annotations;
+            constants.push(constant);
+
+            if !self.consume_optional(TokenKind::Comma) {
+                break;
+            }
         }
 
+        self.consume_optional(TokenKind::SemiColon);
+        Some(constants)
     }
 
-    fn consume_optional(&mut self, kind: TokenKind) -> bool {
+    fn parse_class_kind(&mut self) -> Option<ClassKind> {
         let pos = self.pos;
         let token = self.consume_token();
 
-        if token.kind == kind {
-            true
-        } else {
-            self.pos = pos;
-            false
+        match token.kind {
+            TokenKind::ClassKeyword => Some(ClassKind::Class),
+            TokenKind::InterfaceKeyword => Some(ClassKind::Interface),
+            TokenKind::EnumKeyword => Some(ClassKind::Enum),
+            TokenKind::At => {
+                self.consume_expected(TokenKind::InterfaceKeyword)?;
+                Some(ClassKind::Annotation)
+            },
+            // `record` is only a keyword in front of a type declaration
+            TokenKind::Identifier if token.text == "record" => Some(ClassKind::Record),
+            _ => {
+                self.report_diagnostic(
+                    format!("Unexpected Token of kind {:?}, expected a class, interface, enum or record declaration",
+                            token.kind),
+                    token.start, token.end);
+                self.pos = pos;
+                None
+            }
         }
     }
 
-    fn parse_keywords(&mut self) -> Vec<Token> {
-        let mut keywords = Vec::new();
-        
-        while self.curr_token().kind.is_keyword() {
-            keywords.push(self.consume_token());
-        }
-
-        keywords
-    }
+    /// Parses one member of the body of `class`. Local and anonymous classes
+    /// found in method bodies and field initializers end up in `class.nested`.
+    fn parse_def(&mut self, class: &mut Class) -> Option<Declaration> {
+        let (keywords, annotations) = self.parse_keywords();
 
+        if self.at_class_decl() {
+            let mut nested = self.parse_class_decl(Some(&class.name))?;
+            nested.modifiers = modifiers_from_keywords(&keywords);
+            nested.annotations = annotations;
+            return Some(Declaration::Class(nested));
+        }
 
-    fn parse_def(&mut self) -> Option<Declaration> {
-        let keywords = self.parse_keywords();
         let capsulation = {
             let caps_keyword = keywords
                                .iter()
@@ -256,103 +425,363 @@ impl Parser<'_> {
                                       || k.kind == TokenKind::PublicKeyword
                                       || k.kind == TokenKind::ProtectedKeyword);
 
-            if caps_keyword.is_some() {
-                match caps_keyword.unwrap().kind {
-                    TokenKind::PrivateKeyword => Capsulation::Private,
-                    TokenKind::PublicKeyword => Capsulation::Public,
-                    TokenKind::ProtectedKeyword => Capsulation::Protected,
-                    _ => unreachable!()
-                }
-            } else {
-                Capsulation::Package
+            match caps_keyword.map(|k| k.kind) {
+                Some(TokenKind::PrivateKeyword) => Capsulation::Private,
+                Some(TokenKind::PublicKeyword) => Capsulation::Public,
+                Some(TokenKind::ProtectedKeyword) => Capsulation::Protected,
+                _ => Capsulation::Package,
             }
         };
 
-        let field_type = self.consume_expected(TokenKind::Identifier)?;
+        let type_parameters = self.parse_type_parameters()?;
+
+        // The compact canonical constructor of a record, `Point { ... }`, takes the record components
+        let simple_name = class.name.rsplit('.').next().unwrap_or(&class.name);
+        if class.kind == ClassKind::Record && self.curr_token().text == simple_name
+           && self.nth_token(1).kind == TokenKind::OpenCurly {
+            let name = self.consume_token().text.to_string();
+            self.consume_token();
+
+            let mut body_types = Vec::new();
+            let local_classes = self.skip_code(&class.name, false, &mut body_types);
+            class.nested.extend(local_classes);
+
+            let params = class.record_components.iter()
+                                                .map(|c| Parameter::new(c.param_type.clone(), c.name.clone()))
+                                                .collect();
+            let mut method = Method::new_constructor(name, params, capsulation);
+            method.modifiers = modifiers_from_keywords(&keywords);
+            method.annotations = annotations;
+            method.body_types = body_types;
+            return Some(Declaration::Method(method));
+        }
+
+        let field_type = self.parse_type()?;
         let (name, is_constructor) = if self.curr_token().kind == TokenKind::OpenParen {
-            (field_type.clone(), true)
+            (field_type.name.clone(), true)
         } else {
-            (self.consume_expected(TokenKind::Identifier)?, false)
+            (self.consume_expected(TokenKind::Identifier)?.text.to_string(), false)
         };
 
-        if self.consume_optional(TokenKind::OpenParen) {
-            let mut start_pos = self.pos;
-            let mut params = Vec::new();
+        if self.curr_token().kind == TokenKind::OpenParen {
+            let params = self.parse_parameters()?;
 
-            while self.curr_token().kind != TokenKind::CloseParen {
-                let param_type = self.consume_expected(TokenKind::Identifier)?;
-                let name = self.consume_expected(TokenKind::Identifier)?;
-                let param = Parameter(Type(param_type.text), name.text);
+            // Exceptions are not part of the diagram
+            if self.curr_token().kind == TokenKind::Identifier && self.curr_token().text == "throws" {
+                self.consume_token();
+                loop {
+                    self.parse_type()?;
 
-                params.push(param);
+                    if !self.consume_optional(TokenKind::Comma) {
+                        break;
+                    }
+                }
+            }
 
-                if self.pos == start_pos {
-                    break;
+            if self.consume_optional(TokenKind::DefaultKeyword) {
+                // Default value of an annotation type element
+                let local_classes = self.skip_code(&class.name, true, &mut Vec::new());
+                class.nested.extend(local_classes);
+            }
+
+            let mut body_types = Vec::new();
+            if !self.consume_optional(TokenKind::SemiColon) {
+                self.consume_expected(TokenKind::OpenCurly)?;
+                let local_classes = self.skip_code(&class.name, false, &mut body_types);
+                class.nested.extend(local_classes);
+            }
+
+            let mut method = if is_constructor {
+                Method::new_constructor(name, params, capsulation)
+            } else {
+                Method::new(name, field_type, params, capsulation)
+            };
+            method.type_parameters = type_parameters;
+            method.modifiers = modifiers_from_keywords(&keywords);
+            method.annotations = annotations;
+            method.body_types = body_types;
+
+            Some(Declaration::Method(method))
+        } else {
+            // `Item a = null, b[];` declares one field per name, each with its own array dimensions
+            let mut fields = Vec::new();
+            let mut name = name;
+
+            loop {
+                let mut field_type = field_type.clone();
+                while self.consume_optional(TokenKind::OpenBracket) {
+                    self.consume_expected(TokenKind::CloseBracket)?;
+                    field_type.array_dimensions += 1;
+                }
+
+                if self.consume_optional(TokenKind::Equal) {
+                    let local_classes = self.skip_code(&class.name, true, &mut class.initializer_types);
+                    class.nested.extend(local_classes);
                 }
 
-                start_pos = self.pos;
+                let mut field = Field::new(field_type, name, capsulation);
+                field.modifiers = modifiers_from_keywords(&keywords);
+                field.annotations = annotations.clone();
+                fields.push(field);
 
-                if self.curr_token().kind != TokenKind::Comma {
+                if !self.consume_optional(TokenKind::Comma) {
                     break;
                 }
-
-                self.consume_expected(TokenKind::Comma)?;
+                name = self.consume_expected(TokenKind::Identifier)?.text.to_string();
             }
 
-            self.consume_expected(TokenKind::CloseParen)?;
+            self.consume_expected(TokenKind::SemiColon)?;
+            Some(Declaration::Fields(fields))
+        }
+    }
 
-            if !self.consume_optional(TokenKind::SemiColon) {
-                self.consume_expected(TokenKind::OpenCurly)?;
-                let mut curly_stack = 1;
-                let mut curr;
-
-                while {
-                    curr = self.curr_chr();
-                    curly_stack != 0 && curr != '\0'
-                }{
-                    match curr {
-                        '}' => curly_stack -= 1,
-                        '{' => curly_stack += 1,
-                        _ => ()
+    /// Whether the upcoming tokens start a class, interface, enum, record or annotation type.
+    fn at_class_decl(&self) -> bool {
+        let token = self.curr_token();
+
+        match token.kind {
+            TokenKind::ClassKeyword | TokenKind::InterfaceKeyword | TokenKind::EnumKeyword => true,
+            TokenKind::At => self.nth_token(1).kind == TokenKind::InterfaceKeyword,
+            TokenKind::Identifier => token.text == "record" && self.nth_token(1).kind == TokenKind::Identifier,
+            _ => false,
+        }
+    }
+
+    /// Skips the tokens of a method body or field initializer. Stops behind the `}` that
+    /// closes an already consumed `{`, or, with `is_expression` set, in front of the first
+    /// `;` or `,` outside of any brackets. Local and anonymous classes are parsed on the way,
+    /// the types the code refers to are added to `types`.
+    fn skip_code(&mut self, outer: &str, is_expression: bool, types: &mut Vec<Type>) -> Vec<Class> {
+        let mut depth = if is_expression { 0 } else { 1 };
+        // Open parentheses and square brackets, whose commas separate arguments and not declarators
+        let mut parens = 0usize;
+        let mut classes = Vec::new();
+        let mut prev = TokenKind::OpenCurly;
+
+        loop {
+            types.extend(self.scan_type_use(prev));
+
+            let start = self.pos;
+            let token = self.consume_token();
+
+            let class_decl = match token.kind {
+                TokenKind::Eof => break,
+                TokenKind::SemiColon | TokenKind::CloseCurly if is_expression && depth == 0 => {
+                    self.pos = start;
+                    break;
+                },
+                TokenKind::Comma if is_expression && depth == 0 && parens == 0 => {
+                    self.pos = start;
+                    break;
+                },
+                TokenKind::OpenParen | TokenKind::OpenBracket => {
+                    parens += 1;
+                    false
+                },
+                TokenKind::CloseParen | TokenKind::CloseBracket => {
+                    parens = parens.saturating_sub(1);
+                    false
+                },
+                // Explicit type arguments of a call like `Map.<K, V>of()`
+                TokenKind::Less if prev == TokenKind::Dot => {
+                    self.skip_balanced(TokenKind::Less, TokenKind::Greater);
+                    false
+                },
+                TokenKind::OpenCurly => {
+                    depth += 1;
+                    false
+                },
+                TokenKind::CloseCurly => {
+                    depth -= 1;
+                    // An initializer goes on after its lambda bodies and array initializers
+                    if depth == 0 && !is_expression {
+                        break;
                     }
-                    self.pos += 1;
-                }
-            }
+                    false
+                },
+                // `Foo.class` is a class literal
+                TokenKind::ClassKeyword | TokenKind::InterfaceKeyword | TokenKind::EnumKeyword => prev != TokenKind::Dot,
+                // Variables named `record` are far more common than local records
+                TokenKind::Identifier => token.text == "record" && prev != TokenKind::Dot
+                                         && self.curr_token().kind == TokenKind::Identifier
+                                         && matches!(self.nth_token(1).kind, TokenKind::OpenParen | TokenKind::Less),
+                _ => false,
+            };
 
-            Some(Declaration::Method(
-                if is_constructor {
-                    Method::new_constructor(name.text, params, capsulation)
+            let diagnostic_count = self.diagnostics.len();
+            let class = if class_decl {
+                self.pos = start;
+                self.parse_class_decl(Some(outer))
+            } else if token.kind == TokenKind::Identifier && token.text == "new" {
+                types.extend(self.scan_created_type());
+                if self.at_anonymous_class() {
+                    self.parse_anonymous_class(outer)
                 } else {
-                    Method::new(name.text, Type(field_type.text), params, capsulation)
-                }))
-        } else {
-            if self.curr_token().kind == TokenKind::Equal {
-                self.consume_token();
-                while self.curr_chr() != ';' && self.curr_chr() != '\0' {
-                    self.pos += 1;
+                    self.skip_created_type();
+                    None
+                }
+            } else {
+                None
+            };
+
+            // A declaration that does not parse was just code after all
+            match class {
+                Some(class) => {
+                    classes.push(class);
+                    prev = TokenKind::CloseCurly;
+                },
+                None => {
+                    if class_decl {
+                        self.diagnostics.truncate(diagnostic_count);
+                        self.pos = start;
+                        self.consume_token();
+                    }
+                    prev = token.kind;
                 }
             }
+        }
 
-            self.consume_expected(TokenKind::SemiColon)?;
-            Some(Declaration::Field(Field::new(Type(field_type.text), name.text, capsulation)))
+        classes
+    }
+
+    /// Looks for a type used by the code at the current position without consuming anything:
+    /// a static call like `Foo.bar()`, a cast like `(Foo) bar` or a local variable declaration
+    /// like `Foo foo = bar` at the start of a statement. Whatever looks like a type is taken,
+    /// names that are not classes of the project are dropped during resolution anyway.
+    fn scan_type_use(&mut self, prev: TokenKind) -> Option<Type> {
+        let token = self.curr_token();
+
+        if token.kind == TokenKind::Identifier && prev != TokenKind::Dot
+           && token.text.starts_with(char::is_uppercase)
+           && self.nth_token(1).kind == TokenKind::Dot
+           && self.nth_token(2).kind == TokenKind::Identifier
+           && self.nth_token(3).kind == TokenKind::OpenParen {
+            return Some(Type::simple(token.text.to_string()));
+        }
+
+        let at_statement = matches!(prev,
+            TokenKind::OpenCurly | TokenKind::CloseCurly | TokenKind::SemiColon | TokenKind::OpenParen |
+            TokenKind::FinalKeyword);
+        if token.kind != TokenKind::Identifier || !at_statement {
+            return None;
+        }
+
+        self.look_ahead(|parser| {
+            let used_type = parser.parse_type()?;
+
+            let is_type = match parser.curr_token().kind {
+                TokenKind::Identifier => match parser.nth_token(1).kind {
+                    TokenKind::Equal | TokenKind::SemiColon | TokenKind::Comma | TokenKind::CloseParen => true,
+                    // The `:` of an enhanced for loop
+                    TokenKind::Operator => parser.nth_token(1).text == ":",
+                    _ => false,
+                },
+                TokenKind::CloseParen if prev == TokenKind::OpenParen => matches!(parser.nth_token(1).kind,
+                    TokenKind::Identifier | TokenKind::OpenParen | TokenKind::StringLiteral | TokenKind::TextBlock |
+                    TokenKind::CharLiteral | TokenKind::NumberLiteral),
+                _ => false,
+            };
+
+            if is_type { Some(used_type) } else { None }
+        })
+    }
+
+    /// The type after a `new` keyword, without consuming it. Array creations like
+    /// `new Foo[size]` only yield the element type.
+    fn scan_created_type(&mut self) -> Option<Type> {
+        self.look_ahead(|parser| {
+            let pos = parser.pos;
+
+            parser.parse_type().or_else(|| {
+                parser.pos = pos;
+                parser.parse_qualified_name().map(Type::simple)
+            })
+        })
+    }
+
+    /// Moves past the type after a `new` keyword, whose type arguments like the ones of
+    /// `new HashMap<K, V>()` hold commas. Array creations with a size stop at the brackets.
+    fn skip_created_type(&mut self) {
+        if let Some(end) = self.look_ahead(|parser| parser.parse_type().map(|_| parser.pos)) {
+            self.pos = end;
         }
     }
 
-    pub fn parse_class_def(&mut self) -> Option<Class> {
-        self.parse_keywords();
-        self.consume_expected(TokenKind::ClassKeyword)?;
-        let name = self.consume_expected(TokenKind::Identifier)?;
-        self.consume_expected(TokenKind::OpenCurly)?;
+    /// Whether `Type(args) {` follows a `new` keyword, checked quietly as most create objects or arrays.
+    fn at_anonymous_class(&mut self) -> bool {
+        self.look_ahead(|parser| {
+            parser.parse_type().is_some() && parser.consume_optional(TokenKind::OpenParen) && {
+                parser.skip_balanced(TokenKind::OpenParen, TokenKind::CloseParen);
+                parser.curr_token().kind == TokenKind::OpenCurly
+            }
+        })
+    }
+
+    /// Parses the rest of `new Type(args) { body }` after the `new` keyword.
+    fn parse_anonymous_class(&mut self, outer: &str) -> Option<Class> {
+        let supertype = self.parse_type()?;
+        self.consume_expected(TokenKind::OpenParen)?;
+        self.skip_balanced(TokenKind::OpenParen, TokenKind::CloseParen);
+
+        if !self.consume_optional(TokenKind::OpenCurly) {
+            return None;
+        }
+
+        self.anonymous_count += 1;
+        let mut class = Class::new(format!("{}${}", outer, self.anonymous_count), Vec::new(), Vec::new());
+        class.superclass = Some(supertype);
+
+        self.parse_class_body(&mut class);
+        Some(class)
+    }
+
+    /// Skips the rest of a broken member: up to and including the next `;` or block,
+    /// or up to the `}` that closes the surrounding class.
+    fn synchronize(&mut self) {
+        loop {
+            let pos = self.pos;
+            let token = self.consume_token();
+
+            match token.kind {
+                TokenKind::Eof | TokenKind::SemiColon => break,
+                TokenKind::OpenCurly => {
+                    self.skip_balanced(TokenKind::OpenCurly, TokenKind::CloseCurly);
+                    break;
+                },
+                TokenKind::CloseCurly => {
+                    self.pos = pos;
+                    break;
+                },
+                _ => ()
+            }
+        }
+    }
+
+    /// Parses the members of a class body up to and including the closing `}`.
+    /// Broken members are reported and skipped, so the class is still usable.
+    fn parse_class_body(&mut self, class: &mut Class) {
         let mut start_pos = self.pos;
-        let mut fields = Vec::new();
-        let mut methods = Vec::new();
 
-        while self.curr_token().kind != TokenKind::CloseCurly {
-            let def = self.parse_def()?;
+        while self.curr_token().kind != TokenKind::CloseCurly && self.curr_token().kind != TokenKind::Eof {
+            if self.consume_optional(TokenKind::SemiColon) {
+                continue;
+            }
 
-            match def {
-                Declaration::Field(field) => fields.push(field),
-                Declaration::Method(method) => methods.push(method),
+            // Static and instance initializer blocks
+            let pos = self.pos;
+            self.parse_keywords();
+            if self.consume_optional(TokenKind::OpenCurly) {
+                let local_classes = self.skip_code(&class.name, false, &mut class.initializer_types);
+                class.nested.extend(local_classes);
+                continue;
+            }
+            self.pos = pos;
+
+            match self.parse_def(class) {
+                Some(Declaration::Fields(fields)) => class.fields.extend(fields),
+                Some(Declaration::Method(method)) => class.methods.push(method),
+                Some(Declaration::Class(nested)) => class.nested.push(nested),
+                None => self.synchronize(),
             }
 
             if self.pos == start_pos {
@@ -362,8 +791,268 @@ impl Parser<'_> {
             start_pos = self.pos;
         }
 
-        self.consume_expected(TokenKind::CloseCurly)?;
+        self.consume_expected(TokenKind::CloseCurly);
+    }
+
+    /// Parses a type declaration after its modifiers. Nested types get the name of
+    /// their `outer` type as a prefix, e.g. `Outer.Inner`.
+    fn parse_class_decl(&mut self, outer: Option<&str>) -> Option<Class> {
+        let kind = self.parse_class_kind()?;
+        let name = self.consume_expected(TokenKind::Identifier)?;
+        let type_parameters = self.parse_type_parameters()?;
+
+        let record_components = if kind == ClassKind::Record {
+            self.parse_parameters()?
+        } else {
+            Vec::new()
+        };
+
+        let mut superclass = None;
+        let mut interfaces = Vec::new();
+
+        if kind == ClassKind::Interface && self.consume_optional(TokenKind::ExtendsKeyword) {
+            loop {
+                interfaces.push(self.parse_type()?);
+
+                if !self.consume_optional(TokenKind::Comma) {
+                    break;
+                }
+            }
+        } else if self.consume_optional(TokenKind::ExtendsKeyword) {
+            superclass = Some(self.parse_type()?);
+        }
+
+        if self.consume_optional(TokenKind::ImplementsKeyword) {
+            loop {
+                interfaces.push(self.parse_type()?);
+
+                if !self.consume_optional(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        // The subclasses of a sealed type point to it themselves
+        if self.curr_token().kind == TokenKind::Identifier && self.curr_token().text == "permits" {
+            self.consume_token();
+            loop {
+                self.parse_type()?;
+
+                if !self.consume_optional(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume_expected(TokenKind::OpenCurly)?;
+
+        let name = match outer {
+            Some(outer) => format!("{}.{}", outer, name.text),
+            None => name.text.to_string(),
+        };
+
+        let mut class = Class::new(name, Vec::new(), Vec::new());
+        class.kind = kind;
+        class.type_parameters = type_parameters;
+        class.record_components = record_components;
+        class.superclass = superclass;
+        class.interfaces = interfaces;
+
+        if kind == ClassKind::Enum {
+            class.enum_constants = self.parse_enum_constants()?;
+        }
+
+        self.parse_class_body(&mut class);
+
+        // Interface members without a visibility keyword are public, fields are constants
+        // and interface methods without a body are abstract
+        if kind == ClassKind::Interface || kind == ClassKind::Annotation {
+            for field in class.fields.iter_mut() {
+                if field.capsulation == Capsulation::Package {
+                    field.capsulation = Capsulation::Public;
+                }
+                field.modifiers.is_static = true;
+                field.modifiers.is_final = true;
+            }
+
+            for method in class.methods.iter_mut() {
+                if method.capsulation == Capsulation::Package {
+                    method.capsulation = Capsulation::Public;
+                }
+                if kind == ClassKind::Interface && !method.modifiers.is_default && !method.modifiers.is_static
+                   && method.capsulation != Capsulation::Private {
+                    method.modifiers.is_abstract = true;
+                }
+            }
+        }
+
+        Some(class)
+    }
+
+    pub fn parse_class_def(&mut self) -> Option<Class> {
+        let (keywords, annotations) = self.parse_keywords();
+        self.anonymous_count = 0;
+
+        let mut class = self.parse_class_decl(None)?;
+        class.modifiers = modifiers_from_keywords(&keywords);
+        class.annotations = annotations;
+        Some(class)
+    }
+
+    fn parse_package_decl(&mut self) -> Option<String> {
+        self.consume_expected(TokenKind::PackageKeyword)?;
+        let name = self.parse_qualified_name()?;
+        self.consume_expected(TokenKind::SemiColon)?;
+        Some(name)
+    }
+
+    fn parse_import(&mut self) -> Option<Import> {
+        self.consume_expected(TokenKind::ImportKeyword)?;
+        let is_static = self.consume_optional(TokenKind::StaticKeyword);
+        let name = self.parse_qualified_name()?;
+
+        let is_wildcard = self.curr_token().kind == TokenKind::Dot && self.nth_token(1).text == "*";
+        if is_wildcard {
+            self.consume_token();
+            self.consume_token();
+        }
+
+        self.consume_expected(TokenKind::SemiColon)?;
+        Some(Import::new(name, is_static, is_wildcard))
+    }
+
+    pub fn parse_compilation_unit(&mut self) -> CompilationUnit {
+        let mut unit = CompilationUnit::new();
+
+        while self.curr_token().kind != TokenKind::Eof {
+            let pos = self.pos;
+
+            if self.curr_token().kind == TokenKind::PackageKeyword {
+                match self.parse_package_decl() {
+                    Some(package) => unit.package = Some(package),
+                    None => self.synchronize(),
+                }
+            } else if self.curr_token().kind == TokenKind::ImportKeyword {
+                match self.parse_import() {
+                    Some(import) => unit.imports.push(import),
+                    None => self.synchronize(),
+                }
+            } else if !self.consume_optional(TokenKind::SemiColon) {
+                match self.parse_class_def() {
+                    Some(class) => unit.classes.push(class),
+                    None => self.synchronize(),
+                }
+            }
+
+            // A stray `}` is neither a declaration nor skipped by the recovery
+            if self.pos == pos {
+                self.consume_token();
+            }
+        }
+
+        for class in &mut unit.classes {
+            assign_package(class, &unit.package);
+        }
+
+        // Lexer problems were found up front, report everything in source order
+        self.diagnostics.sort_by_key(|d| d.start);
+        unit
+    }
+}
+
+fn assign_package(class: &mut Class, package: &Option<String>) {
+    class.package = package.clone();
+
+    for nested in &mut class.nested {
+        assign_package(nested, package);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> (CompilationUnit, Vec<Diagnostic>) {
+        let mut parser = Parser::new(source);
+        let unit = parser.parse_compilation_unit();
+        (unit, parser.diagnostics)
+    }
+
+    fn member_names(class: &Class) -> (Vec<&str>, Vec<&str>) {
+        (class.fields.iter().map(|f| f.name.as_str()).collect(),
+         class.methods.iter().map(|m| m.name.as_str()).collect())
+    }
+
+    #[test]
+    fn braces_in_method_bodies() {
+        let (unit, diagnostics) = parse(r#"
+            class A {
+                void f() {
+                    String s = "}";
+                    char c = '}';
+                    char q = '\'';
+                    String t = """
+                        \""" }
+                        """;
+                    /* } */ // }
+                }
+                int x;
+                void g() {}
+            }
+        "#);
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(member_names(&unit.classes[0]), (vec!["x"], vec!["f", "g"]));
+    }
+
+    #[test]
+    fn lambdas_in_initializers() {
+        let (unit, diagnostics) = parse("
+            class A {
+                Runnable r = () -> { int y; };
+                long n = list.stream().filter(s -> { return s.isEmpty(); }).count();
+                int[][] m = { { 1 }, { 2 } };
+                int x;
+            }
+        ");
+
+        assert!(diagnostics.is_empty());
+        assert_eq!(member_names(&unit.classes[0]), (vec!["r", "n", "m", "x"], vec![]));
+    }
+
+    #[test]
+    fn several_declarators() {
+        let (unit, diagnostics) = parse("
+            class A {
+                Item a = null, b = null;
+                private Item first, second;
+                int c[], d[][] = { { 1, 2 } };
+                Map<String, Item> e = new HashMap<String, Item>(f(1, 2)), g = Map.<String, Item>of();
+                Runnable h = () -> { int i, j; }, k = null;
+            }
+        ");
+
+        assert!(diagnostics.is_empty());
+        let class = &unit.classes[0];
+        assert_eq!(member_names(class).0, ["a", "b", "first", "second", "c", "d", "e", "g", "h", "k"]);
+        assert_eq!(class.fields[3].capsulation, Capsulation::Private);
+        assert_eq!(class.fields[4].field_type.array_dimensions, 1);
+        assert_eq!(class.fields[5].field_type.array_dimensions, 2);
+    }
+
+    #[test]
+    fn broken_member_is_left_out() {
+        let (unit, diagnostics) = parse("
+            class A {
+                int x;
+                int broken(;
+                void g() {}
+            }
+            class B {}
+        ");
 
-        Some(Class::new(name.text, fields, methods))
+        assert!(diagnostics.iter().any(|d| d.is_error()));
+        assert_eq!(unit.classes.len(), 2);
+        assert_eq!(member_names(&unit.classes[0]), (vec!["x"], vec!["g"]));
     }
 }
@@ -0,0 +1,208 @@
+use crate::java::*;
+use crate::relation::*;
+use crate::diagram::{annotation_stereotypes, DiagramOptions};
+
+/// Writes the classes and every relation between them as a PlantUML class diagram. Nested classes
+/// are always connected to their outer class, PlantUML cannot draw them inside of it.
+pub fn export(classes: &[&Class], options: &DiagramOptions) -> String {
+    let all_classes = classes.iter().flat_map(|c| c.flatten()).collect::<Vec<_>>();
+
+    // Classes are named by their qualified names, whose dots must not create packages of their own
+    let mut out = "@startuml\nset separator none\n".to_string();
+
+    let mut packages: Vec<Option<&str>> = Vec::new();
+    for class in &all_classes {
+        if !packages.contains(&class.package.as_deref()) {
+            packages.push(class.package.as_deref());
+        }
+    }
+
+    for package in packages {
+        let indent = if package.is_some() { "    " } else { "" };
+
+        out.push('\n');
+        if let Some(package) = package {
+            out.push_str(&format!("package {} {{\n", package));
+        }
+        for class in all_classes.iter().filter(|c| c.package.as_deref() == package) {
+            out.push_str(&class_declaration(class, indent, options));
+        }
+        if package.is_some() {
+            out.push_str("}\n");
+        }
+    }
+
+    let relations = all_relations(&all_classes, true, options.infer_ownership);
+    if !relations.is_empty() {
+        out.push('\n');
+    }
+    for relation in &relations {
+        out.push_str(&relation_line(&all_classes, relation));
+        out.push('\n');
+    }
+
+    out.push_str("@enduml\n");
+    out
+}
+
+fn class_keyword(class: &Class) -> &'static str {
+    match class.kind {
+        ClassKind::Interface => "interface",
+        ClassKind::Enum => "enum",
+        ClassKind::Annotation => "annotation",
+        ClassKind::Class | ClassKind::Record if class.modifiers.is_abstract => "abstract class",
+        ClassKind::Class | ClassKind::Record => "class",
+    }
+}
+
+/// `class "Name<T>" as qualified.Name <<stereotypes>> { ... }`, with the members in the order of the diagram.
+fn class_declaration(class: &Class, indent: &str, options: &DiagramOptions) -> String {
+    let mut name = class.name.clone();
+    if !class.type_parameters.is_empty() {
+        name.push_str(&format!("<{}>", type_parameters_to_string(&class.type_parameters)));
+    }
+
+    let mut out = format!("{}{} \"{}\" as {}", indent, class_keyword(class), name, class.qualified_name());
+
+    // Records have no keyword of their own, the other kinds are told apart by theirs
+    let mut stereotypes = annotation_stereotypes(&class.annotations, options);
+    if class.kind == ClassKind::Record {
+        stereotypes.insert(0, "record".to_string());
+    }
+    for stereotype in stereotypes {
+        out.push_str(&format!(" <<{}>>", stereotype));
+    }
+
+    let mut rows = Vec::new();
+
+    for constant in &class.enum_constants {
+        // Arguments would make PlantUML take the constant for a method
+        let field = if constant.arguments.is_some() { "{field} " } else { "" };
+        rows.push(format!("{}{}{}", field, stereotype_prefix(&constant.annotations, options), constant));
+    }
+    for component in &class.record_components {
+        rows.push(format!("{}{}", stereotype_prefix(&component.annotations, options), component));
+    }
+    for field in class.fields.iter().filter(|f| !options.fields_as_edges_only || field_targets(f).is_empty()) {
+        let mut row = member_row(field.to_string(), &field.annotations, field.modifiers, options);
+        if options.show_read_only && field.modifiers.is_final {
+            row.push_str(" {readOnly}");
+        }
+        rows.push(row);
+    }
+    for method in &class.methods {
+        rows.push(member_row(method.to_string(), &method.annotations, method.modifiers, options));
+    }
+
+    if rows.is_empty() {
+        out.push('\n');
+    } else {
+        out.push_str(" {\n");
+        for row in rows {
+            out.push_str(&format!("{}    {}\n", indent, row));
+        }
+        out.push_str(&format!("{}}}\n", indent));
+    }
+
+    out
+}
+
+fn stereotype_prefix(annotations: &[Annotation], options: &DiagramOptions) -> String {
+    let stereotypes = annotation_stereotypes(annotations, options);
+
+    if stereotypes.is_empty() {
+        String::new()
+    } else {
+        format!("<<{}>> ", stereotypes.join(", "))
+    }
+}
+
+/// Adds `{static}`, `{abstract}` and the stereotypes to a field or method row after its visibility marker,
+/// which PlantUML only recognizes at the very start: `+ {static} count: int`.
+fn member_row(row: String, annotations: &[Annotation], modifiers: Modifiers, options: &DiagramOptions) -> String {
+    let (marker, rest) = row.split_at(2);
+    let mut out = marker.to_string();
+
+    if modifiers.is_static {
+        out.push_str("{static} ");
+    }
+    if modifiers.is_abstract {
+        out.push_str("{abstract} ");
+    }
+    out.push_str(&stereotype_prefix(annotations, options));
+    out.push_str(rest);
+
+    out
+}
+
+/// One relation with the arrow pointing from `from` to `to` like in the rendered diagram.
+fn relation_line(classes: &[&Class], relation: &Relation) -> String {
+    let from = classes[relation.from].qualified_name();
+    let to = classes[relation.to].qualified_name();
+
+    let arrow = match relation.kind {
+        RelationKind::Generalization => return format!("{} <|-- {}", to, from),
+        RelationKind::Realization => return format!("{} <|.. {}", to, from),
+        RelationKind::Nesting => return format!("{} +-- {}", to, from),
+        RelationKind::Dependency => return format!("{} ..> {}", from, to),
+        RelationKind::Association => "-->",
+        RelationKind::Aggregation => "o--",
+        RelationKind::Composition => "*--",
+    };
+
+    let mut out = format!("{} {}", from, arrow);
+    if let Some(multiplicity) = &relation.multiplicity {
+        out.push_str(&format!(" \"{}\"", multiplicity));
+    }
+    out.push_str(&format!(" {}", to));
+    if let Some(role) = &relation.role {
+        out.push_str(&format!(" : {}", role));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> CompilationUnit {
+        Parser::new(source).parse_compilation_unit()
+    }
+
+    #[test]
+    fn relation_arrows() {
+        let unit = parse("package shop; class Item {} class Shop {}");
+        let classes = unit.classes.iter().collect::<Vec<_>>();
+        let line = |kind| relation_line(&classes, &Relation::new(1, 0, kind));
+
+        assert_eq!(line(RelationKind::Generalization), "shop.Item <|-- shop.Shop");
+        assert_eq!(line(RelationKind::Realization), "shop.Item <|.. shop.Shop");
+        assert_eq!(line(RelationKind::Nesting), "shop.Item +-- shop.Shop");
+        assert_eq!(line(RelationKind::Dependency), "shop.Shop ..> shop.Item");
+        assert_eq!(line(RelationKind::Aggregation), "shop.Shop o-- shop.Item");
+
+        let mut relation = Relation::new(1, 0, RelationKind::Association);
+        relation.multiplicity = Some("0..*".to_string());
+        relation.role = Some("items".to_string());
+        assert_eq!(relation_line(&classes, &relation), "shop.Shop --> \"0..*\" shop.Item : items");
+    }
+
+    #[test]
+    fn member_modifiers_follow_the_visibility() {
+        let mut options = DiagramOptions::new();
+        options.stereotype_annotations = vec!["Id".to_string()];
+
+        let mut modifiers = Modifiers::new();
+        modifiers.is_static = true;
+        let annotations = [Annotation::new("Id".to_string(), None)];
+        assert_eq!(member_row("+ count: int".to_string(), &annotations, modifiers, &options),
+                   "+ {static} <<Id>> count: int");
+
+        let mut modifiers = Modifiers::new();
+        modifiers.is_abstract = true;
+        assert_eq!(member_row("# run(): void".to_string(), &[], modifiers, &options), "# {abstract} run(): void");
+        assert_eq!(member_row("~ name: String".to_string(), &[], Modifiers::new(), &options), "~ name: String");
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::java::{Class, CompilationUnit};
+use crate::parser::Parser;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::resolve::resolve_types;
+
+pub struct Project {
+    pub units: Vec<CompilationUnit>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Self {
+            units: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Loads java files and every java file below source roots, `-` being a file read from stdin,
+    /// and resolves the types between them.
+    pub fn load(inputs: &[PathBuf]) -> io::Result<Self> {
+        let mut project = Self::new();
+
+        for input in inputs {
+            if input.as_os_str() == "-" {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source)?;
+                project.add_source(&source, Path::new("<stdin>"));
+                continue;
+            }
+
+            for path in collect_java_files(input)? {
+                project.add_file(&path)?;
+            }
+        }
+
+        resolve_types(&mut project.units);
+        Ok(project)
+    }
+
+    /// The top-level classes of every file.
+    pub fn classes(&self) -> Vec<&Class> {
+        self.units.iter().flat_map(|u| u.classes.iter()).collect()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+
+    /// Reads and parses the file at `path`. Files that are not UTF-8 are read as Latin-1, the encoding
+    /// of most older sources, with a warning at the first character that is not valid UTF-8.
+    pub fn add_file(&mut self, path: &Path) -> io::Result<()> {
+        let bytes = fs::read(path).map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?;
+
+        match String::from_utf8(bytes) {
+            Ok(source) => self.add_source(&source, path),
+            Err(err) => {
+                let invalid = err.utf8_error().valid_up_to();
+                let bytes = err.into_bytes();
+                let source = bytes.iter().map(|&b| b as char).collect::<String>();
+
+                let start = bytes[..invalid].iter().map(|&b| (b as char).len_utf8()).sum::<usize>();
+                let end = start + (bytes[invalid] as char).len_utf8();
+
+                let mut diagnostic = Diagnostic::new(Severity::Warning,
+                                                     "File is not valid UTF-8, reading it as Latin-1".to_string(),
+                                                     &source, start, end);
+                diagnostic.file = Some(path.to_path_buf());
+                self.diagnostics.push(diagnostic);
+
+                self.add_source(&source, path);
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Parses `source`, reporting its diagnostics as coming from `path`.
+    pub fn add_source(&mut self, source: &str, path: &Path) {
+        let mut parser = Parser::new(source);
+
+        self.units.push(parser.parse_compilation_unit());
+        self.diagnostics.extend(parser.diagnostics
+                                      .into_iter()
+                                      .map(|mut d| {
+                                          d.file = Some(path.to_path_buf());
+                                          d
+                                      }));
+    }
+}
+
+pub fn collect_java_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    if root.is_dir() {
+        walk_dir(root, &mut files)?;
+        files.sort();
+    } else {
+        files.push(root.to_path_buf());
+    }
+
+    Ok(files)
+}
+
+fn walk_dir(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            walk_dir(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "java") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,294 @@
+use crate::java::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelationKind {
+    Generalization,
+    Realization,
+    Nesting,
+    Association,
+    Aggregation,
+    Composition,
+    Dependency,
+}
+
+/// An edge between two classes, stored as indices into the class list.
+#[derive(Debug)]
+pub struct Relation {
+    pub from: usize,
+    pub to: usize,
+    pub kind: RelationKind,
+    /// Role of `to` and how many instances `from` holds, shown at the end of associations.
+    pub role: Option<String>,
+    pub multiplicity: Option<String>,
+}
+
+impl Relation {
+    pub fn new(from: usize, to: usize, kind: RelationKind) -> Self {
+        Self {
+            from, to, kind, role: None, multiplicity: None,
+        }
+    }
+}
+
+/// Library types holding any number of their type arguments.
+const COLLECTIONS: &[&str] = &[
+    "Iterable", "Collection", "List", "ArrayList", "LinkedList", "Set", "HashSet", "LinkedHashSet", "TreeSet",
+    "SortedSet", "Queue", "Deque", "ArrayDeque", "PriorityQueue", "Map", "HashMap", "LinkedHashMap", "TreeMap",
+    "SortedMap",
+];
+
+/// Finds the class a resolved type refers to, see `resolve::resolve_types`.
+fn find_class(classes: &[&Class], class_type: &Type) -> Option<usize> {
+    let qualified_name = class_type.qualified_name.as_ref()?;
+    classes.iter().position(|c| &c.qualified_name() == qualified_name)
+}
+
+/// Collects `extends` and `implements` edges between classes of the project.
+/// Supertypes that are not part of the project (e.g. library classes) are skipped.
+pub fn inheritance_relations(classes: &[&Class]) -> Vec<Relation> {
+    let mut relations = Vec::new();
+
+    for (i, class) in classes.iter().enumerate() {
+        if let Some(superclass) = &class.superclass {
+            if let Some(to) = find_class(classes, superclass) {
+                // Anonymous classes "extend" interfaces as well
+                let kind = if classes[to].kind == ClassKind::Interface && class.kind != ClassKind::Interface {
+                    RelationKind::Realization
+                } else {
+                    RelationKind::Generalization
+                };
+
+                relations.push(Relation::new(i, to, kind));
+            }
+        }
+
+        // An interface extending another interface is a generalization as well
+        let kind = if class.kind == ClassKind::Interface {
+            RelationKind::Generalization
+        } else {
+            RelationKind::Realization
+        };
+
+        for interface in &class.interfaces {
+            if let Some(to) = find_class(classes, interface) {
+                relations.push(Relation::new(i, to, kind));
+            }
+        }
+    }
+
+    relations
+}
+
+/// Connects every nested class with its enclosing class.
+pub fn nesting_relations(classes: &[&Class]) -> Vec<Relation> {
+    let mut relations = Vec::new();
+
+    for (i, class) in classes.iter().enumerate() {
+        for nested in &class.nested {
+            if let Some(from) = classes.iter().position(|c| std::ptr::eq(*c, nested)) {
+                relations.push(Relation::new(from, i, RelationKind::Nesting));
+            }
+        }
+    }
+
+    relations
+}
+
+/// Project types a field refers to, together with their multiplicity. Arrays and collections
+/// hold `0..*` of their elements (both keys and values for maps), `Optional` holds `0..1`.
+/// Fields of primitive or library types refer to nothing.
+pub fn field_targets(field: &Field) -> Vec<(&Type, &'static str)> {
+    let field_type = &field.field_type;
+
+    if field_type.qualified_name.is_some() {
+        let multiplicity = if field_type.array_dimensions > 0 { "0..*" } else { "1" };
+        return vec![(field_type, multiplicity)];
+    }
+
+    // Arrays of collections are not looked into
+    if field_type.array_dimensions > 0 {
+        return Vec::new();
+    }
+
+    let simple_name = field_type.name.rsplit('.').next().unwrap_or("");
+    let multiplicity = if COLLECTIONS.contains(&simple_name) {
+        "0..*"
+    } else if simple_name == "Optional" {
+        "0..1"
+    } else {
+        return Vec::new();
+    };
+
+    field_type.arguments.iter()
+                        .filter_map(|a| match a {
+                            TypeArgument::Type(t) => Some(t),
+                            TypeArgument::Wildcard(Some(WildcardBound::Extends(t))) => Some(t),
+                            _ => None,
+                        })
+                        .filter(|t| t.qualified_name.is_some() && t.array_dimensions == 0)
+                        .map(|t| (t, multiplicity))
+                        .collect()
+}
+
+/// Turns fields referring to other classes of the project into associations labelled with the
+/// field name. A field alone does not tell who owns the referred object, so only with `ownership`
+/// set, final fields become compositions and other fields holding many instances aggregations.
+pub fn field_relations(classes: &[&Class], ownership: bool) -> Vec<Relation> {
+    let mut relations = Vec::new();
+
+    for (i, class) in classes.iter().enumerate() {
+        for field in &class.fields {
+            for (target, multiplicity) in field_targets(field) {
+                if let Some(to) = find_class(classes, target) {
+                    let kind = if !ownership {
+                        RelationKind::Association
+                    } else if field.modifiers.is_final {
+                        RelationKind::Composition
+                    } else if multiplicity == "0..*" {
+                        RelationKind::Aggregation
+                    } else {
+                        RelationKind::Association
+                    };
+
+                    let mut relation = Relation::new(i, to, kind);
+                    relation.role = Some(field.name.clone());
+                    relation.multiplicity = Some(multiplicity.to_string());
+                    relations.push(relation);
+                }
+            }
+        }
+    }
+
+    relations
+}
+
+/// `used` and all types in its type arguments.
+fn type_with_arguments<'a>(used: &'a Type, types: &mut Vec<&'a Type>) {
+    types.push(used);
+
+    for argument in &used.arguments {
+        match argument {
+            TypeArgument::Type(t) => type_with_arguments(t, types),
+            TypeArgument::Wildcard(Some(WildcardBound::Extends(t))) => type_with_arguments(t, types),
+            TypeArgument::Wildcard(Some(WildcardBound::Super(t))) => type_with_arguments(t, types),
+            TypeArgument::Wildcard(None) => (),
+        }
+    }
+}
+
+/// Connects every class with the classes it uses: in parameter and return types, and in its method
+/// bodies and initializers through `new`, static calls, local variables and casts. Classes it already
+/// has one of the `existing` relations with are left out, so a dependency only shows plain use.
+pub fn dependency_relations(classes: &[&Class], existing: &[Relation]) -> Vec<Relation> {
+    let mut relations: Vec<Relation> = Vec::new();
+
+    for (i, class) in classes.iter().enumerate() {
+        let mut used = Vec::new();
+
+        for method in &class.methods {
+            type_with_arguments(&method.return_type, &mut used);
+            for parameter in &method.parameters {
+                type_with_arguments(&parameter.param_type, &mut used);
+            }
+            for body_type in &method.body_types {
+                type_with_arguments(body_type, &mut used);
+            }
+        }
+
+        for initializer_type in &class.initializer_types {
+            type_with_arguments(initializer_type, &mut used);
+        }
+
+        for used_type in used {
+            if let Some(to) = find_class(classes, used_type) {
+                let is_known = existing.iter().chain(relations.iter()).any(|r| r.from == i && r.to == to);
+
+                if to != i && !is_known {
+                    relations.push(Relation::new(i, to, RelationKind::Dependency));
+                }
+            }
+        }
+    }
+
+    relations
+}
+
+/// Every relation between the classes: inheritance, nesting if `with_nesting` is set, fields and the
+/// dependencies not covered by any of them. `ownership` is passed on to `field_relations`.
+pub fn all_relations(classes: &[&Class], with_nesting: bool, ownership: bool) -> Vec<Relation> {
+    let mut relations = inheritance_relations(classes);
+    if with_nesting {
+        relations.extend(nesting_relations(classes));
+    }
+    relations.extend(field_relations(classes, ownership));
+    let dependencies = dependency_relations(classes, &relations);
+    relations.extend(dependencies);
+
+    relations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolve::resolve_types;
+
+    fn parse(source: &str) -> CompilationUnit {
+        let mut units = vec![Parser::new(source).parse_compilation_unit()];
+        resolve_types(&mut units);
+        units.remove(0)
+    }
+
+    fn targets<'c>(class: &'c Class, field: &str) -> Vec<(&'c str, &'static str)> {
+        let field = class.fields.iter().find(|f| f.name == field).unwrap();
+        field_targets(field).into_iter().map(|(t, m)| (t.name.as_str(), m)).collect()
+    }
+
+    const SHOP: &str = "
+        class Item {}
+        class Shop {
+            Item one;
+            final Item owned;
+            List<Item> list;
+            Optional<Item> maybe;
+            Item[] array;
+            Map<String, Item> byName;
+            Map<Item, Shop> both;
+            List<? extends Item> bounded;
+            List<Item>[] buckets;
+            List<String> names;
+        }
+    ";
+
+    #[test]
+    fn field_multiplicities() {
+        let unit = parse(SHOP);
+        let shop = &unit.classes[1];
+
+        assert_eq!(targets(shop, "one"), [("Item", "1")]);
+        assert_eq!(targets(shop, "list"), [("Item", "0..*")]);
+        assert_eq!(targets(shop, "maybe"), [("Item", "0..1")]);
+        assert_eq!(targets(shop, "array"), [("Item", "0..*")]);
+        assert_eq!(targets(shop, "byName"), [("Item", "0..*")]);
+        assert_eq!(targets(shop, "both"), [("Item", "0..*"), ("Shop", "0..*")]);
+        assert_eq!(targets(shop, "bounded"), [("Item", "0..*")]);
+        assert!(targets(shop, "buckets").is_empty());
+        assert!(targets(shop, "names").is_empty());
+    }
+
+    #[test]
+    fn ownership_is_opt_in() {
+        let unit = parse(SHOP);
+        let classes = unit.classes.iter().collect::<Vec<_>>();
+
+        let kind = |ownership, role: &str| {
+            field_relations(&classes, ownership).into_iter().find(|r| r.role.as_deref() == Some(role)).unwrap().kind
+        };
+
+        assert!(field_relations(&classes, false).iter().all(|r| r.kind == RelationKind::Association));
+        assert_eq!(kind(true, "one"), RelationKind::Association);
+        assert_eq!(kind(true, "owned"), RelationKind::Composition);
+        assert_eq!(kind(true, "list"), RelationKind::Aggregation);
+        assert_eq!(kind(true, "maybe"), RelationKind::Association);
+    }
+}
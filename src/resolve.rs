@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+
+use crate::java::*;
+
+/// Looks up the simple and partially qualified type names of one compilation unit
+/// the way javac does: nested classes of the enclosing classes first, then single-type
+/// imports, the own package and finally wildcard imports.
+struct Resolver<'a> {
+    /// Qualified names of every class in the project.
+    known: &'a HashSet<String>,
+    package: &'a Option<String>,
+    imports: &'a [Import],
+    /// Qualified names of the classes around the current declaration, innermost last.
+    enclosing: Vec<String>,
+    /// Type variables in scope, which shadow classes of the same name.
+    type_variables: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(known: &'a HashSet<String>, package: &'a Option<String>, imports: &'a [Import]) -> Self {
+        Self {
+            known,
+            package,
+            imports,
+            enclosing: Vec::new(),
+            type_variables: Vec::new(),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        // Only the first segment is looked up, `Outer.Inner` continues from wherever `Outer` is
+        let (first, rest) = match name.find('.') {
+            Some(i) => (&name[..i], &name[i..]),
+            None => (name, ""),
+        };
+
+        if rest.is_empty() && self.type_variables.iter().any(|v| v == first) {
+            return None;
+        }
+
+        let mut candidates = self.enclosing.iter()
+                                           .rev()
+                                           .map(|e| format!("{}.{}", e, first))
+                                           .collect::<Vec<_>>();
+
+        candidates.extend(self.imports.iter()
+                                      .filter(|i| !i.is_wildcard && i.simple_name() == first)
+                                      .map(|i| i.name.clone()));
+
+        candidates.push(match self.package {
+            Some(package) => format!("{}.{}", package, first),
+            None => first.to_string(),
+        });
+
+        candidates.extend(self.imports.iter()
+                                      .filter(|i| i.is_wildcard)
+                                      .map(|i| format!("{}.{}", i.name, first)));
+
+        // Already fully qualified names are tried last
+        candidates.push(first.to_string());
+
+        candidates.into_iter()
+                  .map(|c| c + rest)
+                  .find(|c| self.known.contains(c))
+    }
+
+    fn resolve_type(&self, resolved: &mut Type) {
+        resolved.qualified_name = self.lookup(&resolved.name);
+
+        for argument in &mut resolved.arguments {
+            match argument {
+                TypeArgument::Type(t) => self.resolve_type(t),
+                TypeArgument::Wildcard(Some(WildcardBound::Extends(t))) => self.resolve_type(t),
+                TypeArgument::Wildcard(Some(WildcardBound::Super(t))) => self.resolve_type(t),
+                TypeArgument::Wildcard(None) => (),
+            }
+        }
+    }
+
+    fn resolve_type_parameters(&mut self, type_parameters: &mut [TypeParameter]) {
+        self.type_variables.extend(type_parameters.iter().map(|p| p.name.clone()));
+
+        for bound in type_parameters.iter_mut().flat_map(|p| p.bounds.iter_mut()) {
+            self.resolve_type(bound);
+        }
+    }
+
+    fn resolve_method(&mut self, method: &mut Method) {
+        let variable_count = self.type_variables.len();
+        self.resolve_type_parameters(&mut method.type_parameters);
+
+        self.resolve_type(&mut method.return_type);
+        for parameter in &mut method.parameters {
+            self.resolve_type(&mut parameter.param_type);
+        }
+        for body_type in &mut method.body_types {
+            self.resolve_type(body_type);
+        }
+
+        self.type_variables.truncate(variable_count);
+    }
+
+    fn resolve_class(&mut self, class: &mut Class) {
+        let variable_count = self.type_variables.len();
+        self.resolve_type_parameters(&mut class.type_parameters);
+
+        // Supertypes are looked up from outside of the class
+        for supertype in class.superclass.iter_mut().chain(class.interfaces.iter_mut()) {
+            self.resolve_type(supertype);
+        }
+
+        self.enclosing.push(class.qualified_name());
+
+        for component in &mut class.record_components {
+            self.resolve_type(&mut component.param_type);
+        }
+
+        for field in &mut class.fields {
+            self.resolve_type(&mut field.field_type);
+        }
+
+        for initializer_type in &mut class.initializer_types {
+            self.resolve_type(initializer_type);
+        }
+
+        for method in &mut class.methods {
+            self.resolve_method(method);
+        }
+
+        for nested in &mut class.nested {
+            self.resolve_class(nested);
+        }
+
+        self.enclosing.pop();
+        self.type_variables.truncate(variable_count);
+    }
+}
+
+/// Fills in `Type::qualified_name` of every type in the signatures and bodies of the project
+/// that refers to one of its classes. Library types stay unresolved.
+pub fn resolve_types(units: &mut [CompilationUnit]) {
+    let known = units.iter()
+                     .flat_map(|u| u.classes.iter())
+                     .flat_map(|c| c.flatten())
+                     .map(|c| c.qualified_name())
+                     .collect::<HashSet<_>>();
+
+    for unit in units.iter_mut() {
+        let mut resolver = Resolver::new(&known, &unit.package, &unit.imports);
+
+        for class in &mut unit.classes {
+            resolver.resolve_class(class);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn resolve(sources: &[&str]) -> Vec<CompilationUnit> {
+        let mut units = sources.iter().map(|s| Parser::new(s).parse_compilation_unit()).collect::<Vec<_>>();
+        resolve_types(&mut units);
+        units
+    }
+
+    fn field_target<'u>(unit: &'u CompilationUnit, field: &str) -> Option<&'u str> {
+        let field = unit.classes[0].fields.iter().find(|f| f.name == field).unwrap();
+        field.field_type.qualified_name.as_deref()
+    }
+
+    #[test]
+    fn same_simple_name_in_two_packages() {
+        let units = resolve(&[
+            "package a; public class Item {}",
+            "package b; public class Item {}",
+            "package b; class Cart { Item item; }",
+            "package c; import a.Item; class Shop { Item item; b.Item other; }",
+        ]);
+
+        assert_eq!(field_target(&units[2], "item"), Some("b.Item"));
+        assert_eq!(field_target(&units[3], "item"), Some("a.Item"));
+        assert_eq!(field_target(&units[3], "other"), Some("b.Item"));
+    }
+
+    #[test]
+    fn single_type_imports_before_package_before_wildcards() {
+        let units = resolve(&[
+            "package a; public class Item {}",
+            "package b; public class Item {}",
+            "package c; import a.*; import b.Item; class Shop { Item item; }",
+            "package b; import a.*; class Cart { Item item; }",
+            "package c; import a.*; class Order { Item item; }",
+        ]);
+
+        assert_eq!(field_target(&units[2], "item"), Some("b.Item"));
+        assert_eq!(field_target(&units[3], "item"), Some("b.Item"));
+        assert_eq!(field_target(&units[4], "item"), Some("a.Item"));
+    }
+}
@@ -0,0 +1,538 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::Range;
+
+/// Minimal distance between a routed line and the boxes it passes.
+const CLEARANCE: i32 = 10;
+/// Distance between the parallel lines running around the same box.
+const ROUTE_SPACING: i32 = 20;
+/// Number of lines considered on every side of a box.
+const ROUTE_LINES: i32 = 3;
+/// Length of the straight piece leaving and entering a box, long enough for an arrowhead.
+const STUB_LENGTH: i32 = 30;
+const BEND_COST: u32 = 60;
+/// Extra cost per pixel and edge of running along a line another edge already uses.
+const OVERLAP_COST: u32 = 4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self {
+        Self {
+            x, y, w, h
+        }
+    }
+
+    fn left(&self) -> i32 {
+        self.x as i32
+    }
+
+    fn right(&self) -> i32 {
+        (self.x + self.w) as i32
+    }
+
+    fn top(&self) -> i32 {
+        self.y as i32
+    }
+
+    fn bottom(&self) -> i32 {
+        (self.y + self.h) as i32
+    }
+
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.left() < other.right() && other.left() < self.right()
+        && self.top() < other.bottom() && other.top() < self.bottom()
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        self.left() <= other.left() && other.right() <= self.right()
+        && self.top() <= other.top() && other.bottom() <= self.bottom()
+    }
+
+    /// Whether the horizontal or vertical segment between two points comes closer than `CLEARANCE`.
+    fn blocks(&self, (x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> bool {
+        x1.min(x2) < self.right() + CLEARANCE && x1.max(x2) > self.left() - CLEARANCE
+        && y1.min(y2) < self.bottom() + CLEARANCE && y1.max(y2) > self.top() - CLEARANCE
+    }
+}
+
+/// Directions in the order right, left, down, up.
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Side {
+    /// Index into `DIRECTIONS` pointing away from the box.
+    fn outwards(&self) -> usize {
+        match *self {
+            Self::Right => 0,
+            Self::Left => 1,
+            Self::Bottom => 2,
+            Self::Top => 3,
+        }
+    }
+
+    fn is_horizontal(&self) -> bool {
+        *self == Self::Top || *self == Self::Bottom
+    }
+}
+
+/// The sides of `from` and `to` that face each other.
+fn facing_sides(from: &Rect, to: &Rect) -> (Side, Side) {
+    if to.bottom() < from.top() {
+        (Side::Top, Side::Bottom)
+    } else if to.top() > from.bottom() {
+        (Side::Bottom, Side::Top)
+    } else if to.left() > from.right() {
+        (Side::Right, Side::Left)
+    } else if to.right() < from.left() {
+        (Side::Left, Side::Right)
+    } else {
+        // One box is inside the other, e.g. a nested class drawn inside its outer class
+        (Side::Bottom, Side::Bottom)
+    }
+}
+
+/// One end of an edge on a side of a box.
+struct End {
+    rect: usize,
+    side: Side,
+    /// Center of the other box along the side. Ends are ordered by it, so edges do not cross right at the box.
+    toward: i32,
+    key: usize,
+}
+
+/// Spreads the ends on every box side evenly along it and returns the attachment points in order of `ends`.
+fn attachment_points(rects: &[Rect], ends: &[End]) -> Vec<(i32, i32)> {
+    ends.iter().map(|end| {
+        let side = ends.iter().filter(|e| e.rect == end.rect && e.side == end.side).collect::<Vec<_>>();
+        let index = side.iter().filter(|e| (e.toward, e.key) < (end.toward, end.key)).count() as i32;
+        let count = side.len() as i32;
+
+        let rect = &rects[end.rect];
+        let along = |start: i32, length: u32| start + length as i32 * (index + 1) / (count + 1);
+
+        match end.side {
+            Side::Top => (along(rect.left(), rect.w), rect.top()),
+            Side::Bottom => (along(rect.left(), rect.w), rect.bottom()),
+            Side::Left => (rect.left(), along(rect.top(), rect.h)),
+            Side::Right => (rect.right(), along(rect.top(), rect.h)),
+        }
+    }).collect()
+}
+
+/// Number of grid nodes above which edges are drawn with a single bend instead of being routed.
+const MAX_GRID_NODES: usize = 4_000_000;
+/// Margin around the ends of an edge the search for a way starts with. It grows until a way is found.
+const SEARCH_MARGIN: i32 = 200;
+/// Number of states the search for the cheapest way may visit. Beyond that, the way found by
+/// aiming straight for the goal is taken, which keeps long edges in large diagrams affordable.
+const SEARCH_LIMIT: usize = 10_000;
+/// How much the distance to the goal outweighs the cost so far when aiming straight for it.
+const GREEDY_WEIGHT: u32 = 8;
+/// Number of states aiming straight for the goal may visit, the edge gets a single bend beyond that.
+const GREEDY_LIMIT: usize = 50_000;
+/// How often the margin around the ends may grow before the edge gets a single bend.
+const MAX_MARGIN_GROWTHS: usize = 3;
+const UNVISITED: u32 = u32::MAX;
+
+/// Fewest bends of a way from `from`, heading in `direction`, to `to`, arriving in `goal_direction`.
+/// Arriving in another direction counts as a bend as well.
+fn min_bends(from: (i32, i32), direction: usize, to: (i32, i32), goal_direction: usize) -> u32 {
+    let ((dx, dy), (gx, gy)) = (DIRECTIONS[direction], DIRECTIONS[goal_direction]);
+    let (x, y) = (to.0 - from.0, to.1 - from.1);
+    let ahead = x * dx + y * dy;
+    let aside = x * dy - y * dx;
+
+    if aside == 0 && ahead >= 0 {
+        if direction == goal_direction { 0 } else { 1 }
+    } else if ahead >= 0 && direction / 2 != goal_direction / 2 && x * gx + y * gy > 0 {
+        1
+    } else {
+        2
+    }
+}
+
+/// Index range of the `lines` strictly between `low` and `high`.
+fn lines_between(lines: &[i32], low: i32, high: i32) -> Range<usize> {
+    lines.partition_point(|&line| line <= low)..lines.partition_point(|&line| line < high)
+}
+
+/// Sparse grid of the lines edges may run along. Every node knows how many boxes it lies in
+/// and how many are crossed on the way to its right and lower neighbour.
+struct Grid<'r> {
+    rects: &'r [Rect],
+    xs: Vec<i32>,
+    ys: Vec<i32>,
+    node_blockers: Vec<u16>,
+    right_blockers: Vec<u16>,
+    down_blockers: Vec<u16>,
+    /// Number of routed edges using the segment to the right or lower neighbour.
+    right_usage: Vec<u32>,
+    down_usage: Vec<u32>,
+    /// Search state per (node, direction), kept between searches and only reset where it was touched.
+    costs: Vec<u32>,
+    previous: Vec<u32>,
+    closed: Vec<bool>,
+    touched: Vec<usize>,
+}
+
+impl<'r> Grid<'r> {
+    /// A grid over the sorted and deduplicated lines `xs` and `ys`.
+    fn new(rects: &'r [Rect], xs: Vec<i32>, ys: Vec<i32>) -> Self {
+        let node_count = xs.len() * ys.len();
+        let mut grid = Self {
+            rects,
+            xs,
+            ys,
+            node_blockers: vec![0; node_count],
+            right_blockers: vec![0; node_count],
+            down_blockers: vec![0; node_count],
+            right_usage: vec![0; node_count],
+            down_usage: vec![0; node_count],
+            costs: vec![UNVISITED; node_count * 4],
+            previous: vec![UNVISITED; node_count * 4],
+            closed: vec![false; node_count * 4],
+            touched: Vec::new(),
+        };
+
+        // Only the nodes and segments near a box can be blocked by it, see `Rect::blocks`
+        let (width, height) = (grid.xs.len(), grid.ys.len());
+        for rect in rects {
+            let columns = lines_between(&grid.xs, rect.left() - CLEARANCE, rect.right() + CLEARANCE);
+            let rows = lines_between(&grid.ys, rect.top() - CLEARANCE, rect.bottom() + CLEARANCE);
+
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    grid.node_blockers[row * width + column] += 1;
+                }
+                for column in columns.start.saturating_sub(1)..columns.end.min(width.saturating_sub(1)) {
+                    grid.right_blockers[row * width + column] += 1;
+                }
+            }
+            for row in rows.start.saturating_sub(1)..rows.end.min(height.saturating_sub(1)) {
+                for column in columns.clone() {
+                    grid.down_blockers[row * width + column] += 1;
+                }
+            }
+        }
+
+        grid
+    }
+
+    fn point(&self, node: usize) -> (i32, i32) {
+        (self.xs[node % self.xs.len()], self.ys[node / self.xs.len()])
+    }
+
+    fn node_at(&self, (x, y): (i32, i32)) -> Option<usize> {
+        let column = self.xs.binary_search(&x).ok()?;
+        let row = self.ys.binary_search(&y).ok()?;
+        Some(row * self.xs.len() + column)
+    }
+
+    fn neighbour(&self, node: usize, direction: usize) -> Option<usize> {
+        let (column, row) = (node % self.xs.len(), node / self.xs.len());
+
+        match direction {
+            0 if column + 1 < self.xs.len() => Some(node + 1),
+            1 if column > 0 => Some(node - 1),
+            2 if row + 1 < self.ys.len() => Some(node + self.xs.len()),
+            3 if row > 0 => Some(node - self.xs.len()),
+            _ => None,
+        }
+    }
+
+    /// Blockers and usage of the segment between `node` and its neighbour in `direction`.
+    fn segment(&self, node: usize, neighbour: usize, direction: usize) -> (u16, u32) {
+        match direction {
+            0 => (self.right_blockers[node], self.right_usage[node]),
+            1 => (self.right_blockers[neighbour], self.right_usage[neighbour]),
+            2 => (self.down_blockers[node], self.down_usage[node]),
+            _ => (self.down_blockers[neighbour], self.down_usage[neighbour]),
+        }
+    }
+
+    /// Whether any of the `blockers` boxes near the segment from `a` to `b` is not one of the `ignored` ones.
+    fn is_blocked(&self, blockers: u16, a: usize, b: usize, ignored: &[usize]) -> bool {
+        if blockers == 0 || ignored.is_empty() {
+            return blockers > 0;
+        }
+
+        let (a, b) = (self.point(a), self.point(b));
+        blockers as usize > ignored.iter().filter(|&&r| self.rects[r].blocks(a, b)).count()
+    }
+
+    /// Columns and rows of the nodes at most `margin` away from the bounding box of two points.
+    fn window(&self, (x1, y1): (i32, i32), (x2, y2): (i32, i32), margin: i32) -> (Range<usize>, Range<usize>) {
+        let margin = margin.saturating_add(1);
+        (lines_between(&self.xs, x1.min(x2).saturating_sub(margin), x1.max(x2).saturating_add(margin)),
+         lines_between(&self.ys, y1.min(y2).saturating_sub(margin), y1.max(y2).saturating_add(margin)))
+    }
+
+    fn mark_used(&mut self, path: &[usize]) {
+        for pair in path.windows(2) {
+            let (a, b) = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+
+            if b == a + 1 {
+                self.right_usage[a] += 1;
+            } else {
+                self.down_usage[a] += 1;
+            }
+        }
+    }
+
+    /// A* over (node, direction) states from `start` to `goal` within `window`, so bends can be made expensive.
+    /// Boxes in `ignored` do not block the way. The distance to the goal is weighted by `weight`,
+    /// only 1 finds the cheapest way, any other ignores the lines used by other edges as well.
+    /// Gives up after visiting `limit` states.
+    fn shortest_path(&mut self, (start, start_direction): (usize, usize), (goal, goal_direction): (usize, usize),
+                     ignored: &[usize], (columns, rows): (Range<usize>, Range<usize>), weight: u32,
+                     limit: usize) -> Option<Vec<usize>> {
+        if self.is_blocked(self.node_blockers[start], start, start, ignored)
+           || self.is_blocked(self.node_blockers[goal], goal, goal, ignored) {
+            return None;
+        }
+
+        // Every pixel costs at least 1 and every bend `BEND_COST`, so the plain estimate never overestimates
+        let goal_point = self.point(goal);
+        let estimate = |grid: &Self, state: usize| {
+            let point = grid.point(state / 4);
+            let distance = ((point.0 - goal_point.0).abs() + (point.1 - goal_point.1).abs()) as u32;
+            distance * weight + min_bends(point, state % 4, goal_point, goal_direction) * BEND_COST
+        };
+
+        let mut queue = BinaryHeap::new();
+        let mut best_cost = u32::MAX;
+        let mut best_state = None;
+
+        let start_state = start * 4 + start_direction;
+        self.costs[start_state] = 0;
+        self.touched.push(start_state);
+        // Among equally promising states, the ones further along are tried first
+        queue.push(Reverse((estimate(self, start_state), Reverse(0), start_state)));
+
+        let mut visited = 0;
+
+        while let Some(Reverse((priority, _, state))) = queue.pop() {
+            let (node, direction) = (state / 4, state % 4);
+            let cost = self.costs[state];
+
+            if self.closed[state] {
+                continue;
+            }
+            if priority >= best_cost || visited == limit {
+                break;
+            }
+            self.closed[state] = true;
+            visited += 1;
+
+            if node == goal {
+                let total = if direction == goal_direction { cost } else { cost + BEND_COST };
+                if total < best_cost {
+                    best_cost = total;
+                    best_state = Some(state);
+                }
+                continue;
+            }
+
+            for next_direction in 0..4 {
+                // Turning back is never useful
+                if next_direction == direction ^ 1 {
+                    continue;
+                }
+
+                let neighbour = match self.neighbour(node, next_direction) {
+                    Some(neighbour) => neighbour,
+                    None => continue,
+                };
+                let (column, row) = (neighbour % self.xs.len(), neighbour / self.xs.len());
+                if !columns.contains(&column) || !rows.contains(&row) {
+                    continue;
+                }
+
+                let (blockers, usage) = self.segment(node, neighbour, next_direction);
+                if self.is_blocked(blockers, node, neighbour, ignored)
+                   || self.is_blocked(self.node_blockers[neighbour], neighbour, neighbour, ignored) {
+                    continue;
+                }
+
+                let (from, to) = (self.point(node), self.point(neighbour));
+                let length = ((to.0 - from.0).abs() + (to.1 - from.1).abs()) as u32;
+                let bend = if next_direction == direction { 0 } else { BEND_COST };
+                // Aiming straight for the goal only works if the cost grows no faster than the estimate
+                let overlap = if weight == 1 { OVERLAP_COST * usage } else { 0 };
+                let next_cost = cost + length * (1 + overlap) + bend;
+
+                let next_state = neighbour * 4 + next_direction;
+                if !self.closed[next_state] && next_cost < self.costs[next_state] {
+                    if self.costs[next_state] == UNVISITED {
+                        self.touched.push(next_state);
+                    }
+                    self.costs[next_state] = next_cost;
+                    self.previous[next_state] = state as u32;
+                    queue.push(Reverse((next_cost + estimate(self, next_state), Reverse(next_cost), next_state)));
+                }
+            }
+        }
+
+        // A way found before giving up need not be the cheapest one
+        let path = best_state.filter(|_| visited < limit).map(|mut state| {
+            let mut path = vec![state / 4];
+
+            while self.previous[state] != UNVISITED {
+                state = self.previous[state] as usize;
+                path.push(state / 4);
+            }
+
+            path.reverse();
+            path
+        });
+
+        for state in self.touched.drain(..) {
+            self.costs[state] = UNVISITED;
+            self.previous[state] = UNVISITED;
+            self.closed[state] = false;
+        }
+
+        path
+    }
+}
+
+/// Drops the points in the middle of straight runs.
+fn simplify(points: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
+    let mut result: Vec<(i32, i32)> = Vec::new();
+
+    for point in points {
+        if result.last() == Some(&point) {
+            continue;
+        }
+
+        if result.len() >= 2 {
+            let (a, b) = (result[result.len() - 2], result[result.len() - 1]);
+            if (a.0 == b.0 && b.0 == point.0) || (a.1 == b.1 && b.1 == point.1) {
+                result.pop();
+            }
+        }
+
+        result.push(point);
+    }
+
+    result
+}
+
+/// The way between the stubs `start` and `goal` of an edge leaving and entering its boxes on `sides`.
+/// The search starts close to the stubs and only looks further away if there is no way nearby.
+/// If the cheapest way takes too long to find, the edge aims straight for the goal instead.
+/// Both searches are bounded, so the work per edge does not grow with the size of the diagram.
+fn route_edge(grid: &mut Grid, start: (i32, i32), sides: (Side, Side), goal: (i32, i32),
+              ignored: &[usize]) -> Option<Vec<(i32, i32)>> {
+    let (start_node, goal_node) = (grid.node_at(start)?, grid.node_at(goal)?);
+    let mut margin = SEARCH_MARGIN;
+
+    for _ in 0..=MAX_MARGIN_GROWTHS {
+        let window = grid.window(start, goal, margin);
+        let is_whole_grid = window.0.len() == grid.xs.len() && window.1.len() == grid.ys.len();
+
+        let (start_state, goal_state) = ((start_node, sides.0.outwards()), (goal_node, sides.1.outwards() ^ 1));
+        let path = grid.shortest_path(start_state, goal_state, ignored, window.clone(), 1, SEARCH_LIMIT)
+                       .or_else(|| grid.shortest_path(start_state, goal_state, ignored, window, GREEDY_WEIGHT,
+                                                      GREEDY_LIMIT));
+
+        if let Some(path) = path {
+            grid.mark_used(&path);
+            return Some(path.iter().map(|n| grid.point(*n)).collect());
+        }
+        if is_whole_grid {
+            return None;
+        }
+
+        margin = margin.saturating_mul(4);
+    }
+
+    None
+}
+
+/// Routes every edge `(from, to)` between `rects` with right-angle bends around all other rects,
+/// within a `width` by `height` area. Returns one polyline per edge, starting on the side of
+/// `from` and ending on the side of `to`.
+pub fn route_edges(rects: &[Rect], edges: &[(usize, usize)], width: u32, height: u32) -> Vec<Vec<(i32, i32)>> {
+    let sides = edges.iter().map(|&(from, to)| facing_sides(&rects[from], &rects[to])).collect::<Vec<_>>();
+
+    let center = |rect: &Rect, side: Side| if side.is_horizontal() {
+        rect.left() + rect.w as i32 / 2
+    } else {
+        rect.top() + rect.h as i32 / 2
+    };
+
+    let ends = edges.iter().zip(&sides).enumerate().flat_map(|(i, (&(from, to), &(from_side, to_side)))| vec![
+        End { rect: from, side: from_side, toward: center(&rects[to], from_side), key: i * 2 },
+        End { rect: to, side: to_side, toward: center(&rects[from], to_side), key: i * 2 + 1 },
+    ]).collect::<Vec<_>>();
+
+    let attachments = attachment_points(rects, &ends);
+    let stubs = ends.iter().zip(&attachments).map(|(end, &(x, y))| {
+        let (dx, dy) = DIRECTIONS[end.side.outwards()];
+        (x + dx * STUB_LENGTH, y + dy * STUB_LENGTH)
+    }).collect::<Vec<_>>();
+
+    let mut xs = stubs.iter().map(|s| s.0).collect::<Vec<_>>();
+    let mut ys = stubs.iter().map(|s| s.1).collect::<Vec<_>>();
+
+    for rect in rects {
+        for k in 1..=ROUTE_LINES {
+            xs.push(rect.left() - k * ROUTE_SPACING);
+            xs.push(rect.right() + k * ROUTE_SPACING);
+            ys.push(rect.top() - k * ROUTE_SPACING);
+            ys.push(rect.bottom() + k * ROUTE_SPACING);
+        }
+    }
+
+    xs.retain(|x| (0..=width as i32).contains(x));
+    ys.retain(|y| (0..=height as i32).contains(y));
+    xs.sort();
+    xs.dedup();
+    ys.sort();
+    ys.dedup();
+
+    // Too large diagrams are not worth the memory and time, their edges get a single bend
+    let mut grid = if xs.len() * ys.len() <= MAX_GRID_NODES {
+        Some(Grid::new(rects, xs, ys))
+    } else {
+        None
+    };
+    let mut routes = Vec::new();
+
+    for (i, &(from, to)) in edges.iter().enumerate() {
+        let (start, goal) = (stubs[i * 2], stubs[i * 2 + 1]);
+
+        // Boxes around the ends, like the outer class of a nested one, may be crossed
+        let ignored = (0..rects.len()).filter(|&r| {
+            (r != from && rects[r].contains(&rects[from])) || (r != to && rects[r].contains(&rects[to]))
+        }).collect::<Vec<_>>();
+
+        let middle = match grid.as_mut().and_then(|grid| route_edge(grid, start, sides[i], goal, &ignored)) {
+            Some(middle) => middle,
+            // Without a free way the edge runs straight through
+            None => vec![start, (start.0, goal.1), goal],
+        };
+
+        let mut points = vec![attachments[i * 2]];
+        points.extend(middle);
+        points.push(attachments[i * 2 + 1]);
+        routes.push(simplify(points));
+    }
+
+    routes
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::canvas::{Canvas, Color, Dash, TextStyle};
+use crate::font::Fonts;
+use crate::image::Image;
+
+/// Collects the diagram as SVG elements, with selectable text instead of rendered glyphs.
+pub struct Svg {
+    width: u32,
+    height: u32,
+    elements: Vec<String>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `property` set to `color`, with an opacity attribute if the color is translucent.
+fn paint(property: &str, color: Color) -> String {
+    let mut attributes = format!("{}=\"rgb({},{},{})\"", property, color.r, color.g, color.b);
+    if color.a < 255 {
+        attributes.push_str(&format!(" {}-opacity=\"{:.3}\"", property, color.a as f32 / 255.0));
+    }
+    attributes
+}
+
+fn points_attribute(points: &[(i32, i32)]) -> String {
+    points.iter().map(|(x, y)| format!("{},{}", x, y)).collect::<Vec<_>>().join(" ")
+}
+
+impl Svg {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            elements: Vec::new(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut out: W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+}
+
+impl fmt::Display for Svg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+                     viewBox=\"0 0 {0} {1}\" shape-rendering=\"crispEdges\">",
+                 self.width, self.height)?;
+        writeln!(f, "<rect width=\"{}\" height=\"{}\" fill=\"white\"/>", self.width, self.height)?;
+
+        for element in &self.elements {
+            writeln!(f, "{}", element)?;
+        }
+
+        writeln!(f, "</svg>")
+    }
+}
+
+impl Canvas for Svg {
+    fn with_size(width: u32, height: u32) -> Self {
+        Self::new(width, height)
+    }
+
+    fn measure_text(text: &str, font_size: usize, fonts: &Fonts, style: TextStyle) -> u32 {
+        Image::measure_styled_text(text, font_size, fonts, style)
+    }
+
+    fn draw_text(&mut self, x: u32, y: u32, text: &str, font_size: usize, fonts: &Fonts, style: TextStyle) {
+        // Pin the text to the measured width, so it fits its box even if the viewer substitutes the font
+        let width = Self::measure_text(text, font_size, fonts, style);
+        let family = fonts.regular.family_name().unwrap_or_else(|| "sans-serif".to_string());
+
+        let mut attributes = format!("x=\"{}\" y=\"{}\" font-family=\"{}\" font-size=\"{}\" textLength=\"{}\" \
+                                      lengthAdjust=\"spacingAndGlyphs\" {}",
+                                     x, y + font_size as u32, escape(&family), font_size, width,
+                                     paint("fill", style.color));
+        if style.bold {
+            attributes.push_str(" font-weight=\"bold\"");
+        }
+        if style.italic {
+            attributes.push_str(" font-style=\"italic\"");
+        }
+        if style.underline {
+            attributes.push_str(" text-decoration=\"underline\"");
+        }
+
+        self.elements.push(format!("<text {}>{}</text>", attributes, escape(text)));
+    }
+
+    fn draw_line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, dash: Dash, color: Color) {
+        self.draw_polyline(&[(x1, y1), (x2, y2)], dash, color);
+    }
+
+    /// A single path, so the dashes continue around the bends.
+    fn draw_polyline(&mut self, points: &[(i32, i32)], dash: Dash, color: Color) {
+        let dash = if !dash.is_solid() {
+            format!(" stroke-dasharray=\"{} {}\"", dash.on, dash.off)
+        } else {
+            String::new()
+        };
+
+        let path = points.iter()
+                         .enumerate()
+                         .map(|(i, (x, y))| format!("{} {} {}", if i == 0 { "M" } else { "L" }, x, y))
+                         .collect::<Vec<_>>()
+                         .join(" ");
+
+        self.elements.push(format!("<path d=\"{}\" fill=\"none\" {}{}/>", path, paint("stroke", color), dash));
+    }
+
+    fn draw_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        self.elements.push(format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" {}/>",
+                                   x, y, w, h, paint("stroke", color)));
+    }
+
+    fn draw_filled_rect(&mut self, x: u32, y: u32, w: u32, h: u32, color: Color) {
+        self.elements.push(format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                                   x, y, w, h, paint("fill", color)));
+    }
+
+    fn draw_circle(&mut self, cx: i32, cy: i32, radius: i32, color: Color) {
+        self.elements.push(format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"none\" {}/>",
+                                   cx, cy, radius, paint("stroke", color)));
+    }
+
+    fn draw_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        self.elements.push(format!("<polygon points=\"{}\" fill=\"none\" {}/>",
+                                   points_attribute(points), paint("stroke", color)));
+    }
+
+    fn fill_polygon(&mut self, points: &[(i32, i32)], color: Color) {
+        self.elements.push(format!("<polygon points=\"{}\" {}/>", points_attribute(points), paint("fill", color)));
+    }
+}